@@ -102,15 +102,15 @@ impl MoveCode {
     }
 
     pub fn is_capture(&self) -> bool {
-        match *self {
+        matches!(
+            *self,
             Capture
-            | EnPassant
-            | KnightPromotionCapture
-            | BishopPromotionCapture
-            | RookPromotionCapture
-            | QueenPromotionCapture => true,
-            _ => false,
-        }
+                | EnPassant
+                | KnightPromotionCapture
+                | BishopPromotionCapture
+                | RookPromotionCapture
+                | QueenPromotionCapture
+        )
     }
 
     pub fn promotion(&self) -> Option<PieceType> {
@@ -125,27 +125,27 @@ impl MoveCode {
     }
 
     pub fn is_castle(&self) -> bool {
-        match *self {
-            KingCastle | QueenCastle => true,
-            _ => false,
-        }
+        matches!(*self, KingCastle | QueenCastle)
     }
 
     pub fn is_quiet(&self) -> bool {
-        match *self {
-            QuietMove | DoublePawnPush | KingCastle | QueenCastle => true,
-            _ => false,
-        }
+        matches!(*self, QuietMove | DoublePawnPush | KingCastle | QueenCastle)
     }
 }
 
+/// Plies the move list holds, a search using it cannot go deeper
+pub const MAX_PLY: usize = 128;
+/// More moves than any position has (218)
+const MAX_MOVES: usize = 256;
+
 pub trait AddMove {
     fn add_move_to_ply(&mut self, m: Move);
 }
 
 pub struct MoveList {
-    moves: [Move; 2048],
-    ply_first_move: [usize; 128], // Index of the first move for a given ply
+    moves: Vec<Move>, // MAX_MOVES moves for each of the MAX_PLY plies
+    scores: Vec<i32>, // Ordering score of each move, higher is searched first
+    ply_first_move: [usize; MAX_PLY + 1], // Index of the first move for a given ply
     current_ply: usize,
     total_count: usize,
 }
@@ -154,6 +154,7 @@ impl AddMove for MoveList {
     fn add_move_to_ply(&mut self, m: Move) {
         assert!(self.current_ply != 0);
         self.moves[self.total_count] = m;
+        self.scores[self.total_count] = 0;
         self.total_count += 1;
     }
 }
@@ -164,11 +165,18 @@ impl AddMove for Vec<Move> {
     }
 }
 
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MoveList {
     pub fn new() -> MoveList {
         MoveList {
-            moves: [Move(0); 2048],
-            ply_first_move: [0; 128],
+            moves: vec![Move(0); MAX_PLY * MAX_MOVES],
+            scores: vec![0; MAX_PLY * MAX_MOVES],
+            ply_first_move: [0; MAX_PLY + 1],
             current_ply: 0,
             total_count: 0,
         }
@@ -220,42 +228,41 @@ impl MoveList {
         self.current_ply -= 1;
     }
 
-    /// "Sorts" the ply in place
-    ///
-    /// Optional first move is placed first in the ply
+    /// Sets the ordering score of a move of the current ply, moves are added with a score of 0
+    pub fn set_score(&mut self, index: usize, score: i32) {
+        assert!(self.current_ply != 0);
+        let first_move_index = self.ply_first_move[self.current_ply];
+        assert!(first_move_index + index < self.total_count);
+        self.scores[first_move_index + index] = score;
+    }
+
+    /// Moves the best scored move among the moves of the ply from `index` on to `index` and
+    /// returns it
     ///
-    /// Loud moves are placed before quiet moves
-    pub fn order_ply(&mut self, first: Option<Move>) {
-        // Selection sort
-        let ply = self.current_ply_mut();
-
-        let mut sorted_index = 0;
-
-        // Place first move at the start
-        if let Some(first) = first {
-            for i in 0..ply.len() {
-                if ply[i] == first {
-                    ply.swap(i, 0);
-                    sorted_index += 1;
-                    break;
-                }
-            }
-        }
+    /// This is a selection sort done one step at a time: when the search stops early on a
+    /// cutoff, the rest of the ply is never sorted
+    pub fn pick_move(&mut self, ply: usize, index: usize) -> Move {
+        let size = self.ply_size(ply);
+        assert!(index < size);
+        let first_move_index = self.ply_first_move[ply];
+        let range = first_move_index + index..first_move_index + size;
 
-        // Put loud moves before quiet moves
-        let range = sorted_index..ply.len();
-        for i in range {
-            if !ply[i].code().is_quiet() {
-                ply.swap(i, sorted_index);
-                sorted_index += 1;
+        let mut best = range.start;
+        for i in range.clone() {
+            if self.scores[i] > self.scores[best] {
+                best = i;
             }
         }
+        self.moves.swap(range.start, best);
+        self.scores.swap(range.start, best);
+        self.moves[range.start]
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::state::game_state::GameState;
 
     #[test]
     fn test_move_list() {
@@ -308,7 +315,11 @@ mod tests {
         assert_eq!(move_list.current_ply, 2);
         assert_eq!(move_list.total_count, 6);
 
-        move_list.order_ply(Some(second_ply_moves[2]));
+        move_list.set_score(1, 10);
+        move_list.set_score(2, 20);
+        assert_eq!(move_list.pick_move(2, 0), second_ply_moves[2]);
+        assert_eq!(move_list.pick_move(2, 1), second_ply_moves[1]);
+        assert_eq!(move_list.pick_move(2, 2), second_ply_moves[0]);
         assert_eq!(
             move_list.current_ply(),
             &[
@@ -323,4 +334,19 @@ mod tests {
         assert_eq!(move_list.current_ply, 1);
         assert_eq!(move_list.total_count, 3);
     }
+
+    #[test]
+    fn test_move_list_capacity() {
+        // The position with the most legal moves known, 218
+        let state =
+            GameState::from_fen("R6R/3Q4/1Q4Q1/4Q3/2Q4Q/Q4Q2/pp1Q4/kBNN1KB1 w - - 0 1".to_string());
+        let move_generator = MoveGenerator::new();
+        let mut move_list = MoveList::new();
+        for _ in 0..MAX_PLY {
+            move_list.new_ply();
+            move_generator.get_pseudo_legal_moves(&state, &mut move_list);
+        }
+        assert!(move_list.current_ply().len() >= 218);
+        assert_eq!(move_list.ply_number(), MAX_PLY);
+    }
 }
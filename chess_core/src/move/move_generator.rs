@@ -2,10 +2,10 @@ use crate::color::Color;
 use crate::r#move::MoveCode;
 use crate::square::Square;
 use crate::state::bitboard::BitBoard;
-use crate::state::chess_board::ChessBoardSide;
+use crate::state::chess_board::{ChessBoardSide, PieceType};
 use crate::state::game_state::GameState;
 
-use super::move_maps::MoveMaps;
+use super::move_maps::{MoveMap, MoveMaps};
 use super::{AddMove, Move, MoveList};
 
 struct MoveGeneratorContext<'a, T: AddMove> {
@@ -38,10 +38,17 @@ impl<'a, T: AddMove> MoveGeneratorContext<'a, T> {
     }
 }
 
+#[derive(Clone)]
 pub struct MoveGenerator {
     move_maps: MoveMaps,
 }
 
+impl Default for MoveGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MoveGenerator {
     pub fn new() -> MoveGenerator {
         MoveGenerator {
@@ -73,6 +80,85 @@ impl MoveGenerator {
             MoveGeneratorContext::new(None, state, &self.move_maps);
         ctx.is_square_attacked(square, by_color)
     }
+
+    /// Pieces of both colors attacking a square, sliding pieces are blocked by `occupation`
+    /// instead of the pieces of the state so that captures can be simulated (static exchange
+    /// evaluation). Pieces missing from `occupation` are still returned.
+    pub fn attackers_to(
+        &self,
+        state: &GameState,
+        square: Square,
+        occupation: BitBoard,
+    ) -> BitBoard {
+        let maps = &self.move_maps;
+        let (white, black) = (&state.boards.white, &state.boards.black);
+        let diagonal = diagonal_attacks(maps, square, occupation);
+        let rank_file = rank_file_attacks(maps, square, occupation);
+
+        (diagonal & (white.bishop | white.queen | black.bishop | black.queen))
+            | (rank_file & (white.rook | white.queen | black.rook | black.queen))
+            | (maps.knight[square] & (white.knight | black.knight))
+            | (maps.king[square] & (white.king | black.king))
+            | (maps.black_pawn_attack[square] & white.pawn)
+            | (maps.white_pawn_attack[square] & black.pawn)
+    }
+
+    /// Squares attacked by a piece of `color` on `square`, sliding pieces are blocked by
+    /// `occupation`
+    pub fn attacks(
+        &self,
+        piece: PieceType,
+        color: Color,
+        square: Square,
+        occupation: BitBoard,
+    ) -> BitBoard {
+        let maps = &self.move_maps;
+        match piece {
+            PieceType::Pawn => match color {
+                Color::White => maps.white_pawn_attack[square],
+                Color::Black => maps.black_pawn_attack[square],
+            },
+            PieceType::Knight => maps.knight[square],
+            PieceType::Bishop => diagonal_attacks(maps, square, occupation),
+            PieceType::Rook => rank_file_attacks(maps, square, occupation),
+            PieceType::Queen => {
+                diagonal_attacks(maps, square, occupation)
+                    | rank_file_attacks(maps, square, occupation)
+            }
+            PieceType::King => maps.king[square],
+        }
+    }
+}
+
+fn diagonal_attacks(maps: &MoveMaps, square: Square, occupation: BitBoard) -> BitBoard {
+    ray_in_increasing_direction(&maps.ne_diagonal, square, occupation)
+        | ray_in_increasing_direction(&maps.nw_diagonal, square, occupation)
+        | ray_in_decreasing_direction(&maps.se_diagonal, square, occupation)
+        | ray_in_decreasing_direction(&maps.sw_diagonal, square, occupation)
+}
+
+fn rank_file_attacks(maps: &MoveMaps, square: Square, occupation: BitBoard) -> BitBoard {
+    ray_in_increasing_direction(&maps.n_file, square, occupation)
+        | ray_in_decreasing_direction(&maps.s_file, square, occupation)
+        | ray_in_increasing_direction(&maps.e_rank, square, occupation)
+        | ray_in_decreasing_direction(&maps.w_rank, square, occupation)
+}
+
+/// Squares of the ray up to and including the first blocker
+fn ray_in_increasing_direction(map: &MoveMap, square: Square, occupation: BitBoard) -> BitBoard {
+    let ray = map[square];
+    match (ray & occupation).get_first_square() {
+        Some(blocker) => ray & !map[blocker],
+        None => ray,
+    }
+}
+
+fn ray_in_decreasing_direction(map: &MoveMap, square: Square, occupation: BitBoard) -> BitBoard {
+    let ray = map[square];
+    match (ray & occupation).get_last_square() {
+        Some(blocker) => ray & !map[blocker],
+        None => ray,
+    }
 }
 
 fn capture_in_increasing_direction(
@@ -497,8 +583,10 @@ impl<'a, T: AddMove> MoveGeneratorContext<'a, T> {
 #[cfg(test)]
 mod tests {
     use crate::{
+        color::Color,
         r#move::{MoveGenerator, MoveList},
-        state::game_state::GameState,
+        square::Square,
+        state::{bitboard::BitBoard, chess_board::PieceType, game_state::GameState},
     };
 
     #[test]
@@ -518,4 +606,62 @@ mod tests {
             .count();
         assert_eq!(n_moves, 20);
     }
+
+    #[test]
+    fn test_attackers_to() {
+        // e5 is attacked by the d4 pawn, the f3 knight, the e1 rook and the d6 pawn,
+        // the e1 rook is behind the e2 queen
+        let game_state =
+            GameState::from_fen("4k3/8/3p4/4p3/3P4/5N2/4Q3/4R1K1 w - - 0 1".to_string());
+        let move_generator = MoveGenerator::new();
+        let e5 = Square::new(4, 4);
+        let occupation = game_state.boards.white.union() | game_state.boards.black.union();
+
+        let mut expected = BitBoard::EMPTY;
+        for square in [
+            Square::new(3, 3),
+            Square::new(2, 5),
+            Square::new(1, 4),
+            Square::new(5, 3),
+        ] {
+            expected.set(square);
+        }
+        assert_eq!(
+            move_generator.attackers_to(&game_state, e5, occupation),
+            expected
+        );
+
+        // Without the queen, the rook attacks through the e-file
+        let mut occupation = occupation;
+        occupation.unset(Square::new(1, 4));
+        expected.set(Square::new(0, 4));
+        assert_eq!(
+            move_generator.attackers_to(&game_state, e5, occupation),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_attacks() {
+        let game_state =
+            GameState::from_fen("4k3/8/3p4/4p3/3P4/5N2/4Q3/4R1K1 w - - 0 1".to_string());
+        let move_generator = MoveGenerator::new();
+        let occupation = game_state.boards.white.union() | game_state.boards.black.union();
+        let count = |piece, color, square| {
+            move_generator
+                .attacks(piece, color, square, occupation)
+                .count_ones()
+        };
+
+        // The e2 queen is blocked by the f3 knight and the e5 pawn
+        assert_eq!(count(PieceType::Queen, Color::White, Square::new(1, 4)), 18);
+        // The e1 rook is blocked by the e2 queen and the g1 king
+        assert_eq!(count(PieceType::Rook, Color::White, Square::new(0, 4)), 7);
+        assert_eq!(count(PieceType::Knight, Color::White, Square::new(2, 5)), 8);
+        assert_eq!(count(PieceType::Bishop, Color::White, Square::new(1, 4)), 7);
+        assert_eq!(count(PieceType::King, Color::Black, Square::new(7, 4)), 5);
+        let pawn_attacks =
+            move_generator.attacks(PieceType::Pawn, Color::Black, Square::new(5, 3), occupation);
+        assert!(pawn_attacks.get(Square::new(4, 4)) && pawn_attacks.get(Square::new(4, 2)));
+    }
 }
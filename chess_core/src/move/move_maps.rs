@@ -2,6 +2,7 @@ use std::ops::{Index, IndexMut};
 
 use crate::{square::Square, state::bitboard::BitBoard};
 
+#[derive(Clone)]
 pub struct MoveMap([BitBoard; 64]);
 
 impl Default for MoveMap {
@@ -24,6 +25,7 @@ impl Index<Square> for MoveMap {
     }
 }
 
+#[derive(Clone)]
 pub struct MoveMaps {
     pub knight: MoveMap,
     pub king: MoveMap,
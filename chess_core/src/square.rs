@@ -26,7 +26,7 @@ impl Square {
     }
 
     pub fn iter() -> impl Iterator<Item = Square> {
-        (0..64).map(|x| Square(x))
+        (0..64).map(Square)
     }
 
     pub const fn from_bits(bits: u8) -> Self {
@@ -40,7 +40,7 @@ impl Square {
 
 impl Display for Square {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        const A: u8 = 'a' as u8;
+        const A: u8 = b'a';
         write!(f, "{}{}", (self.file() + A) as char, self.rank() + 1)
     }
 }
@@ -55,12 +55,12 @@ impl TryFrom<&str> for Square {
     type Error = &'static str;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        const A: u8 = 'a' as u8;
-        const H: u8 = 'h' as u8;
-        const ZERO: u8 = '0' as u8;
-        const EIGHT: u8 = '8' as u8;
+        const A: u8 = b'a';
+        const H: u8 = b'h';
+        const ONE: u8 = b'1';
+        const EIGHT: u8 = b'8';
         match *value.as_bytes() {
-            [file @ A..=H, rank @ ZERO..EIGHT] => Ok(Square::new(rank - ZERO - 1, file - A)),
+            [file @ A..=H, rank @ ONE..=EIGHT] => Ok(Square::new(rank - ONE, file - A)),
             _ => Err("Square string malformed."),
         }
     }
@@ -112,3 +112,19 @@ pub enum CastleSide {
     King,
     Queen,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_from() {
+        assert_eq!(Square::try_from("a1"), Ok(Square(0)));
+        assert_eq!(Square::try_from("e4"), Ok(Square(28)));
+        assert_eq!(Square::try_from("h8"), Ok(Square(63)));
+        assert!(Square::try_from("a0").is_err());
+        assert!(Square::try_from("a9").is_err());
+        assert!(Square::try_from("i1").is_err());
+        assert!(Square::try_from("e").is_err());
+    }
+}
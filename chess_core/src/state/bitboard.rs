@@ -28,6 +28,8 @@ pub struct BitBoard(u64);
 impl BitBoard {
     pub const EMPTY: Self = Self(0);
     pub const FULL: Self = Self(0xFFFF_FFFF_FFFF_FFFF);
+    /// b1, a2, d1... a1 is a dark square
+    pub const LIGHT_SQUARES: Self = Self(0x55AA_55AA_55AA_55AA);
 
     pub fn is_empty(&self) -> bool {
         *self == BitBoard::EMPTY
@@ -1,7 +1,7 @@
 use crate::{square::Square, state::bitboard::BitBoard};
 
 /// Enum representing the type of a piece.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PieceType {
     Pawn,
     Knight,
@@ -49,6 +49,13 @@ impl ChessBoardSide {
         self.pawn | self.knight | self.bishop | self.rook | self.queen | self.king
     }
 
+    pub fn piece_at(&self, square: Square) -> Option<PieceType> {
+        self.as_array()
+            .into_iter()
+            .find(|(board, _)| board.get(square))
+            .map(|(_, piece_type)| piece_type)
+    }
+
     pub fn as_array(&self) -> [(&BitBoard, PieceType); 6] {
         [
             (&self.pawn, PieceType::Pawn),
@@ -53,6 +53,54 @@ impl GameState {
         }
     }
 
+    /// Like from_fen, but malformed FENs are reported instead of panicking
+    pub fn try_from_fen(fen: String) -> Result<Self, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let &[board, active_color, castling, en_passant, ref rest @ ..] = &fields[..] else {
+            return Err(format!("Invalid FEN {}: missing fields", fen));
+        };
+        let ranks: Vec<&str> = board.split('/').collect();
+        let valid_rank = |rank: &&str| {
+            let mut files = 0;
+            for c in rank.chars() {
+                match c {
+                    '1'..='8' => files += c.to_digit(10).unwrap(),
+                    'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => {
+                        files += 1
+                    }
+                    _ => return false,
+                }
+            }
+            files == 8
+        };
+        if ranks.len() != 8 || !ranks.iter().all(valid_rank) {
+            return Err(format!("Invalid FEN {}: malformed board", fen));
+        }
+        if board.matches('K').count() != 1 || board.matches('k').count() != 1 {
+            return Err(format!("Invalid FEN {}: each side needs one king", fen));
+        }
+        if !matches!(active_color, "w" | "b") {
+            return Err(format!("Invalid FEN {}: malformed side to move", fen));
+        }
+        if castling != "-" && !castling.chars().all(|c| "KQkq".contains(c)) {
+            return Err(format!("Invalid FEN {}: malformed castling rights", fen));
+        }
+        let en_passant_rank = match en_passant {
+            "-" => None,
+            square => Square::try_from(square).ok().map(|square| square.rank()),
+        };
+        if en_passant != "-" && !matches!(en_passant_rank, Some(2 | 5)) {
+            return Err(format!("Invalid FEN {}: malformed en passant square", fen));
+        }
+        if rest
+            .first()
+            .is_some_and(|halfmove| halfmove.parse::<u8>().is_err())
+        {
+            return Err(format!("Invalid FEN {}: malformed halfmove clock", fen));
+        }
+        Ok(Self::from_fen(fen))
+    }
+
     pub fn to_fen(self) -> String {
         let board_str = self.boards.to_fen();
         let flags = self.flags.to_fen();
@@ -127,6 +175,21 @@ impl GameState {
         hash
     }
 
+    /// Hash of the pawns only, the same for every position with the same pawn structure
+    pub fn pawn_hash(&self, zobrist_numbers: &ZobristNumbers) -> u64 {
+        let mut hash = 0;
+        for (board, hash_board) in [
+            (self.boards.white.pawn, &zobrist_numbers.board.white.pawn),
+            (self.boards.black.pawn, &zobrist_numbers.board.black.pawn),
+        ] {
+            let mut b = board;
+            while let Some(lsb) = b.pop_first_square() {
+                hash ^= hash_board[lsb.0 as usize];
+            }
+        }
+        hash
+    }
+
     pub fn split_boards_mut(&mut self) -> (&mut ChessBoardSide, &mut ChessBoardSide) {
         if self.flags.active_color() == Color::White {
             (&mut self.boards.white, &mut self.boards.black)
@@ -164,6 +227,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_try_from_fen() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(
+            GameState::try_from_fen(fen.to_string()),
+            Ok(GameState::from_fen(fen.to_string()))
+        );
+        let invalid = [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqqbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+        ];
+        for fen in invalid {
+            assert!(GameState::try_from_fen(fen.to_string()).is_err(), "{}", fen);
+        }
+    }
+
     #[test]
     fn test_to_fen() {
         let fens = [
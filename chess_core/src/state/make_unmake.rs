@@ -4,7 +4,7 @@ use crate::{
     square::Square,
     state::{
         bitboard::BitBoard, chess_board::PieceType, flags::StateFlags, game_state::GameState,
-        zobrist_numbers::ZobristNumbers,
+        material_key::MaterialKey, zobrist_numbers::ZobristNumbers,
     },
 };
 
@@ -19,6 +19,12 @@ struct IrreversibleInfo {
 pub struct MakeUnmaker<'a> {
     pub state: &'a mut GameState,
     pub zobrist_hash: u64,
+    /// Hash of the pawns only, for caching pawn structure evaluations
+    pub pawn_hash: u64,
+    /// Material of the position, for endgame evaluations
+    pub material_key: MaterialKey,
+    /// Hashes of the positions before each move made, to detect repetitions
+    pub hash_history: Vec<u64>,
     irreversible_stack: Vec<IrreversibleInfo>,
     zobrist_numbers: ZobristNumbers,
 }
@@ -27,9 +33,14 @@ impl MakeUnmaker<'_> {
     pub fn new(state: &'_ mut GameState) -> MakeUnmaker<'_> {
         let zobrist_numbers = ZobristNumbers::new();
         let zobrist_hash = state.hash(&zobrist_numbers);
+        let pawn_hash = state.pawn_hash(&zobrist_numbers);
+        let material_key = MaterialKey::from_state(state);
         MakeUnmaker {
             state,
             zobrist_hash,
+            pawn_hash,
+            material_key,
+            hash_history: Vec::new(),
             irreversible_stack: Vec::new(),
             zobrist_numbers,
         }
@@ -193,11 +204,16 @@ impl MakeUnmaker<'_> {
         // Remove friendly piece from from_board
         let mut moved_piece_board = &mut BitBoard::EMPTY.clone();
         let mut moved_piece_zobrist: [u64; 64] = [0; 64];
+        let mut pawn_moved = false;
 
         for i in 0..6 {
             if !(*friendly_board_list[i].0 & from_board).is_empty() {
                 *friendly_board_list[i].0 &= !from_board;
                 self.zobrist_hash ^= friendly_zobrist_number_list[i][m.from().0 as usize];
+                if friendly_board_list[i].1 == PieceType::Pawn {
+                    self.pawn_hash ^= friendly_zobrist.pawn[m.from().0 as usize];
+                    pawn_moved = true;
+                }
                 moved_piece_board = friendly_board_list[i].0;
                 moved_piece_zobrist = friendly_zobrist_number_list[i];
                 break;
@@ -213,9 +229,12 @@ impl MakeUnmaker<'_> {
         // }
 
         // If the move is not a promotion, add to_board to moved_piece_board
-        if !m.code().promotion().is_some() {
+        if m.code().promotion().is_none() {
             *moved_piece_board |= to_board;
             self.zobrist_hash ^= moved_piece_zobrist[m.to().0 as usize];
+            if pawn_moved {
+                self.pawn_hash ^= friendly_zobrist.pawn[m.to().0 as usize];
+            }
         } else {
             // Otherwise, add the promotion piece to the board
             let non_capture_promotion = m.code().promotion().unwrap();
@@ -258,6 +277,9 @@ impl MakeUnmaker<'_> {
                 if !(*enemy_board_list[i].0 & temp_to_board).is_empty() {
                     *enemy_board_list[i].0 &= !temp_to_board;
                     self.zobrist_hash ^= enemy_zobrist_number_list[i][temp_to.0 as usize];
+                    if enemy_board_list[i].1 == PieceType::Pawn {
+                        self.pawn_hash ^= enemy_zobrist.pawn[temp_to.0 as usize];
+                    }
                     return Some(enemy_board_list[i].1);
                 }
             }
@@ -269,6 +291,8 @@ impl MakeUnmaker<'_> {
         let halfmove = self.state.halfmove;
         let en_passant = self.state.en_passant;
         let flags = self.state.flags;
+        let pawn_move = self.state.split_boards().0.pawn.get(m.from());
+        self.hash_history.push(self.zobrist_hash);
 
         let mut captured_piece_type = None;
         if m.code().is_castle() {
@@ -281,6 +305,15 @@ impl MakeUnmaker<'_> {
         } else {
             captured_piece_type = self.make_non_castle(m);
         }
+        // Material
+        let color = self.state.flags.active_color();
+        if let Some(captured) = captured_piece_type {
+            self.material_key.remove(!color, captured);
+        }
+        if let Some(promotion) = m.code().promotion() {
+            self.material_key.remove(color, PieceType::Pawn);
+            self.material_key.add(color, promotion);
+        }
         // Stack irreversible info
         self.irreversible_stack.push(IrreversibleInfo {
             halfmove,
@@ -289,10 +322,57 @@ impl MakeUnmaker<'_> {
             captured_piece_type,
         });
 
-        self.state.halfmove += 1;
+        // Fifty move rule counter
+        self.state.halfmove = if pawn_move || m.code().is_capture() {
+            0
+        } else {
+            halfmove.saturating_add(1)
+        };
         self.update_flags(m);
     }
 
+    /// Passes the turn to the other side, used by null move pruning
+    pub fn make_null_move(&mut self) {
+        self.irreversible_stack.push(IrreversibleInfo {
+            halfmove: self.state.halfmove,
+            en_passant: self.state.en_passant,
+            flags: self.state.flags,
+            captured_piece_type: None,
+        });
+        self.hash_history.push(self.zobrist_hash);
+        if !self.state.en_passant.is_empty() {
+            self.zobrist_hash ^= self.zobrist_numbers.en_passant_file[self.get_en_passant_file()];
+        }
+        self.state.en_passant = BitBoard::EMPTY;
+        self.state.halfmove = self.state.halfmove.saturating_add(1);
+        self.state.flags.toggle_active_color();
+        self.zobrist_hash ^= self.zobrist_numbers.active_color;
+    }
+
+    pub fn unmake_null_move(&mut self) {
+        let irreversible_info = self.irreversible_stack.pop().unwrap();
+        self.hash_history.pop();
+        self.state.halfmove = irreversible_info.halfmove;
+        self.state.en_passant = irreversible_info.en_passant;
+        if !self.state.en_passant.is_empty() {
+            self.zobrist_hash ^= self.zobrist_numbers.en_passant_file[self.get_en_passant_file()];
+        }
+        self.state.flags = irreversible_info.flags;
+        self.zobrist_hash ^= self.zobrist_numbers.active_color;
+    }
+
+    /// The current position already occurred since the last capture or pawn move, with the same
+    /// side to move
+    pub fn is_repetition(&self) -> bool {
+        self.hash_history
+            .iter()
+            .rev()
+            .take(self.state.halfmove as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|&hash| hash == self.zobrist_hash)
+    }
+
     fn unmake_castle(&mut self, m: Move) {
         // Color flipped here because it is the color of the side that has moved
         if self.state.flags.active_color() == Color::White {
@@ -352,11 +432,16 @@ impl MakeUnmaker<'_> {
         // Remove moved piece from to_board
         let mut moved_piece_board = &mut BitBoard::EMPTY.clone();
         let mut moved_piece_zobrist: [u64; 64] = [0; 64];
+        let mut pawn_moved = false;
 
         for i in 0..6 {
             if !(*friendly_board_list[i].0 & to_board).is_empty() {
                 *friendly_board_list[i].0 &= !to_board;
                 self.zobrist_hash ^= friendly_board_zobrist_list[i][m.to().0 as usize];
+                if friendly_board_list[i].1 == PieceType::Pawn {
+                    self.pawn_hash ^= friendly_zobrist.pawn[m.to().0 as usize];
+                    pawn_moved = true;
+                }
                 moved_piece_board = friendly_board_list[i].0;
                 moved_piece_zobrist = friendly_board_zobrist_list[i];
                 break;
@@ -372,13 +457,17 @@ impl MakeUnmaker<'_> {
         // }
 
         // If the move is not a promotion, replace the moved piece on from_board
-        if !m.code().promotion().is_some() {
+        if m.code().promotion().is_none() {
             *moved_piece_board |= from_board;
             self.zobrist_hash ^= moved_piece_zobrist[m.from().0 as usize];
+            if pawn_moved {
+                self.pawn_hash ^= friendly_zobrist.pawn[m.from().0 as usize];
+            }
         } else {
             // Otherwise, replace the moved piece with a pawn
             friendly_boards.pawn |= from_board;
             self.zobrist_hash ^= friendly_zobrist.pawn[m.from().0 as usize];
+            self.pawn_hash ^= friendly_zobrist.pawn[m.from().0 as usize];
         }
 
         // If the move is en passant, shift to_board to the captured pawn
@@ -400,7 +489,8 @@ impl MakeUnmaker<'_> {
                 Some(piece_type) => match piece_type {
                     PieceType::Pawn => {
                         enemy_boards.pawn |= temp_to_board;
-                        self.zobrist_hash ^= enemy_zobrist.pawn[temp_to.0 as usize]
+                        self.zobrist_hash ^= enemy_zobrist.pawn[temp_to.0 as usize];
+                        self.pawn_hash ^= enemy_zobrist.pawn[temp_to.0 as usize];
                     }
                     PieceType::Knight => {
                         enemy_boards.knight |= temp_to_board;
@@ -435,6 +525,16 @@ impl MakeUnmaker<'_> {
 
     pub fn unmake_move(&mut self, m: Move) {
         let irreversible_info = self.irreversible_stack.pop().unwrap();
+        self.hash_history.pop();
+        // The side that moved is the passive side
+        let color = !self.state.flags.active_color();
+        if let Some(captured) = irreversible_info.captured_piece_type {
+            self.material_key.add(!color, captured);
+        }
+        if let Some(promotion) = m.code().promotion() {
+            self.material_key.add(color, PieceType::Pawn);
+            self.material_key.remove(color, promotion);
+        }
 
         if m.code().is_castle() {
             self.unmake_castle(m);
@@ -487,6 +587,7 @@ mod tests {
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
             "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
             "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
         ];
         let move_gen = &MoveGenerator::new();
         for fen in fens {
@@ -496,6 +597,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_make_unmake_null_move() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        ];
+        for fen in fens {
+            let state = &mut GameState::from_fen(fen.to_string());
+            let original_gs = *state;
+            let make_unmaker = &mut MakeUnmaker::new(state);
+            let original_hash = make_unmaker.zobrist_hash;
+
+            make_unmaker.make_null_move();
+            assert_ne!(
+                make_unmaker.state.flags.active_color(),
+                original_gs.flags.active_color()
+            );
+            assert!(make_unmaker.state.en_passant.is_empty());
+            assert_eq!(
+                make_unmaker.zobrist_hash,
+                make_unmaker.state.hash(&make_unmaker.zobrist_numbers)
+            );
+
+            make_unmaker.unmake_null_move();
+            assert_eq!(*make_unmaker.state, original_gs);
+            assert_eq!(make_unmaker.zobrist_hash, original_hash);
+        }
+    }
+
+    #[test]
+    fn test_repetition() {
+        let state = &mut GameState::from_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+        );
+        let make_unmaker = &mut MakeUnmaker::new(state);
+        let knight_moves = [
+            Move::new(Square(6), Square(21), MoveCode::QuietMove),
+            Move::new(Square(62), Square(45), MoveCode::QuietMove),
+            Move::new(Square(21), Square(6), MoveCode::QuietMove),
+            Move::new(Square(45), Square(62), MoveCode::QuietMove),
+        ];
+        for m in knight_moves {
+            assert!(!make_unmaker.is_repetition());
+            make_unmaker.make_move(m);
+        }
+        assert!(make_unmaker.is_repetition());
+        assert_eq!(make_unmaker.state.halfmove, 4);
+
+        // A pawn move cannot be undone
+        make_unmaker.unmake_move(knight_moves[3]);
+        make_unmaker.make_move(Move::new(Square(52), Square(44), MoveCode::QuietMove));
+        assert_eq!(make_unmaker.state.halfmove, 0);
+        assert!(!make_unmaker.is_repetition());
+    }
+
     fn recursize_test_make_unmake_move(
         move_gen: &MoveGenerator,
         make_unmaker: &mut MakeUnmaker,
@@ -525,6 +681,20 @@ mod tests {
                     m,
                     original_gs
                 );
+                assert_eq!(
+                    make_unmaker.material_key,
+                    MaterialKey::from_state(&moved_gs),
+                    "Move: {}\nBoard: {:?}",
+                    m,
+                    original_gs
+                );
+                assert_eq!(
+                    make_unmaker.pawn_hash,
+                    moved_gs.pawn_hash(&make_unmaker.zobrist_numbers),
+                    "Move: {}\nBoard: {:?}",
+                    m,
+                    original_gs
+                );
                 recursize_test_make_unmake_move(move_gen, make_unmaker, move_list, depth - 1);
                 make_unmaker.unmake_move(m);
                 assert_eq!(
@@ -539,6 +709,17 @@ mod tests {
                     m,
                     moved_gs
                 );
+                assert_eq!(
+                    MaterialKey::from_state(&original_gs),
+                    make_unmaker.material_key
+                );
+                assert_eq!(
+                    original_gs.pawn_hash(&make_unmaker.zobrist_numbers),
+                    make_unmaker.pawn_hash,
+                    "\nMove: {}\nMade move: {:?}",
+                    m,
+                    moved_gs
+                );
             } else {
                 make_unmaker.unmake_move(m);
             }
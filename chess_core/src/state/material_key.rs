@@ -0,0 +1,127 @@
+use crate::{
+    color::Color,
+    state::{chess_board::PieceType, game_state::GameState},
+};
+
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+
+/// Number of pieces of each type and color, packed 4 bits per count
+///
+/// Positions with the same material have the same key whatever the squares of the pieces, so
+/// the key identifies endings such as KBNvK exactly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MaterialKey(pub u64);
+
+impl MaterialKey {
+    fn shift(color: Color, piece: PieceType) -> u32 {
+        (color as u32 * 6 + piece as u32) * 4
+    }
+
+    pub fn from_state(state: &GameState) -> Self {
+        let mut key = MaterialKey::default();
+        for (color, side) in [
+            (Color::White, &state.boards.white),
+            (Color::Black, &state.boards.black),
+        ] {
+            for (board, piece) in side.as_array() {
+                key.0 += (board.count_ones() as u64) << Self::shift(color, piece);
+            }
+        }
+        key
+    }
+
+    /// Key of a signature such as KBNvK, the pieces of white first
+    pub fn from_signature(signature: &str) -> Option<Self> {
+        let (white, black) = signature.split_once('v')?;
+        let mut key = MaterialKey::default();
+        for (color, pieces) in [(Color::White, white), (Color::Black, black)] {
+            for c in pieces.chars() {
+                let piece = PIECE_TYPES
+                    .into_iter()
+                    .find(|&piece| char::from(piece) == c)?;
+                if key.count(color, piece) == 15 {
+                    return None;
+                }
+                key.add(color, piece);
+            }
+        }
+        Some(key)
+    }
+
+    pub fn count(self, color: Color, piece: PieceType) -> u32 {
+        ((self.0 >> Self::shift(color, piece)) & 0b1111) as u32
+    }
+
+    pub fn add(&mut self, color: Color, piece: PieceType) {
+        self.0 += 1 << Self::shift(color, piece);
+    }
+
+    pub fn remove(&mut self, color: Color, piece: PieceType) {
+        self.0 -= 1 << Self::shift(color, piece);
+    }
+
+    /// Same material with the colors swapped
+    pub fn mirror(self) -> Self {
+        let white = self.0 & 0xff_ffff;
+        let black = (self.0 >> 24) & 0xff_ffff;
+        MaterialKey(black | (white << 24))
+    }
+
+    /// Pieces of `color` other than pawns and the king
+    pub fn piece_count(self, color: Color) -> u32 {
+        PIECE_TYPES[1..5]
+            .iter()
+            .map(|&piece| self.count(color, piece))
+            .sum()
+    }
+}
+
+impl std::fmt::Display for MaterialKey {
+    /// Signature with the pieces of each side from the strongest, such as KRPvKR
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for color in [Color::White, Color::Black] {
+            if color == Color::Black {
+                write!(f, "v")?;
+            }
+            for &piece in PIECE_TYPES.iter().rev() {
+                for _ in 0..self.count(color, piece) {
+                    write!(f, "{}", char::from(piece))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_material_key() {
+        let state = &GameState::from_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+        );
+        let key = MaterialKey::from_state(state);
+        assert_eq!(key.count(Color::White, PieceType::Pawn), 8);
+        assert_eq!(key.count(Color::Black, PieceType::Queen), 1);
+        assert_eq!(key.piece_count(Color::Black), 7);
+        assert_eq!(key.to_string(), "KQRRBBNNPPPPPPPPvKQRRBBNNPPPPPPPP");
+        assert_eq!(key.mirror(), key);
+
+        let state = &GameState::from_fen("8/8/8/3k4/8/8/2N5/2B1K3 w - - 0 1".to_string());
+        let key = MaterialKey::from_state(state);
+        assert_eq!(Some(key), MaterialKey::from_signature("KBNvK"));
+        assert_eq!(Some(key.mirror()), MaterialKey::from_signature("KvKNB"));
+        assert_eq!(key.mirror().to_string(), "KvKBN");
+        assert_eq!(MaterialKey::from_signature("KXvK"), None);
+        assert_eq!(MaterialKey::from_signature("KBNK"), None);
+    }
+}
@@ -3,4 +3,5 @@ pub mod chess_board;
 pub mod flags;
 pub mod game_state;
 pub mod make_unmake;
+pub mod material_key;
 pub mod zobrist_numbers;
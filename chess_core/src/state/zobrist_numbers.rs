@@ -49,6 +49,12 @@ pub struct ZobristNumbers {
     pub en_passant_file: [u64; 8],
 }
 
+impl Default for ZobristNumbers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ZobristNumbers {
     pub fn new() -> Self {
         let rng = &mut ChaCha20Rng::seed_from_u64(SEED);
@@ -0,0 +1,430 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use chess_core::{
+    color::Color,
+    r#move::{Move, MoveGenerator},
+    state::{game_state::GameState, make_unmake::MakeUnmaker},
+};
+use chess_engines::alpha_beta::{
+    evaluation::{Evaluator, NnueEvaluator, SimpleEvaluator},
+    search::{SearchContext, mate_distance},
+    transposition_table::TranspositionTable,
+};
+
+// Self-play training data generation
+//
+// Usage: chess_datagen <output> [--games n] [--threads n] [--depth n | --nodes n]
+//        [--openings path] [--random-plies n] [--nnue path] [--seed n]
+//        [--keep-checks] [--keep-captures]
+//
+// Each game starts from a random line of the openings file (one FEN or EPD per line) or from
+// the starting position, followed by random legal moves, then the engine plays both sides
+// with a fixed depth or node limit. Every position is written on its own line:
+//
+//   <fen> | <score> | <best move> | <result>
+//
+// The score is in centipawns and the result is 1.0, 0.5 or 0.0, both from the point of view of
+// white. The result comes last so that the file can be given to chess_tuner as it is.
+// Positions in check, positions whose best move is a capture and positions with a mate score
+// are skipped, they are not quiet enough for training.
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+/// Games that last longer are drawn
+const MAX_GAME_PLIES: usize = 400;
+const TRANSPOSITION_TABLE_MB: usize = 16;
+/// Random openings tried before giving up, when every line ends the game too soon
+const MAX_OPENING_ATTEMPTS: usize = 1000;
+
+#[derive(Clone, Copy)]
+enum Limit {
+    Depth(u8),
+    Nodes(u64),
+}
+
+struct Options {
+    output: String,
+    games: usize,
+    threads: usize,
+    limit: Limit,
+    openings: Vec<String>,
+    random_plies: usize,
+    nnue: Option<NnueEvaluator>,
+    seed: u64,
+    keep_checks: bool,
+    keep_captures: bool,
+}
+
+/// Position recorded during a game, written once the result is known
+struct Sample {
+    fen: String,
+    /// From the point of view of white
+    score: i32,
+    best_move: Move,
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1).collect());
+    let output = File::create(&options.output).expect("Failed to create the output");
+    let output = Mutex::new(BufWriter::new(output));
+    let started = AtomicUsize::new(0);
+    let positions = AtomicUsize::new(0);
+
+    let result = thread::scope(|scope| {
+        let handles: Vec<_> = (0..options.threads)
+            .map(|id| {
+                let (options, output, started, positions) =
+                    (&options, &output, &started, &positions);
+                scope.spawn(move || -> Result<(), String> {
+                    let random = &mut Random::new(options.seed ^ ((id as u64 + 1) * 0x9e37_79b9));
+                    let transpos = &mut TranspositionTable::new(TRANSPOSITION_TABLE_MB);
+                    loop {
+                        let game = started.fetch_add(1, Ordering::Relaxed);
+                        if game >= options.games {
+                            return Ok(());
+                        }
+                        transpos.clear();
+                        let (samples, result) = match &options.nnue {
+                            Some(nnue) => play_game(options, random, transpos, nnue.clone())?,
+                            None => {
+                                play_game(options, random, transpos, SimpleEvaluator::default())?
+                            }
+                        };
+                        let total = positions.fetch_add(samples.len(), Ordering::Relaxed);
+                        write_samples(&mut *output.lock().unwrap(), &samples, result);
+                        if (game + 1) % 100 == 0 {
+                            eprintln!("{} games, {} positions", game + 1, total + samples.len());
+                        }
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().unwrap())
+    });
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+    output
+        .into_inner()
+        .unwrap()
+        .flush()
+        .expect("Failed to write the output");
+    println!(
+        "{} positions written to {}",
+        positions.into_inner(),
+        options.output
+    );
+}
+
+fn parse_args(args: Vec<String>) -> Options {
+    let usage = "Usage: chess_datagen <output> [--games n] [--threads n] [--depth n | --nodes n] \
+                 [--openings path] [--random-plies n] [--nnue path] [--seed n] \
+                 [--keep-checks] [--keep-captures]";
+    let mut options = Options {
+        output: String::new(),
+        games: 1000,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        limit: Limit::Depth(6),
+        openings: vec![START_FEN.to_string()],
+        random_plies: 8,
+        nnue: None,
+        seed: 1,
+        keep_checks: false,
+        keep_captures: false,
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().expect(usage);
+        match arg.as_str() {
+            "--games" => options.games = value().parse().expect(usage),
+            "--threads" => options.threads = value().parse().expect(usage),
+            "--depth" => options.limit = Limit::Depth(value().parse().expect(usage)),
+            "--nodes" => options.limit = Limit::Nodes(value().parse().expect(usage)),
+            "--openings" => {
+                let openings = std::fs::read_to_string(value()).expect("Failed to read openings");
+                options.openings = openings
+                    .lines()
+                    .map(|line| line.split_whitespace().take(4).collect::<Vec<_>>())
+                    .filter(|fields| fields.len() == 4)
+                    .map(|fields| fields.join(" "))
+                    .collect();
+            }
+            "--random-plies" => options.random_plies = value().parse().expect(usage),
+            "--nnue" => match NnueEvaluator::load(value()) {
+                Ok(nnue) => options.nnue = Some(nnue),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            },
+            "--seed" => options.seed = value().parse().expect(usage),
+            "--keep-checks" => options.keep_checks = true,
+            "--keep-captures" => options.keep_captures = true,
+            _ if options.output.is_empty() => options.output = arg,
+            _ => panic!("{}", usage),
+        }
+    }
+    if options.output.is_empty() || options.openings.is_empty() {
+        panic!("{}", usage);
+    }
+    options
+}
+
+/// Positions of one game and its result for white
+fn play_game<E: Evaluator>(
+    options: &Options,
+    random: &mut Random,
+    transpos: &TranspositionTable,
+    evaluator: E,
+) -> Result<(Vec<Sample>, f64), String> {
+    let move_generator = &MoveGenerator::new();
+    let (mut state, history) = random_opening(options, random, move_generator)?;
+    let make_unmaker = &mut MakeUnmaker::new(&mut state);
+    make_unmaker.hash_history = history;
+    let mut samples = Vec::new();
+
+    for _ in 0..MAX_GAME_PLIES {
+        if let Some(result) = game_result(move_generator, make_unmaker) {
+            return Ok((samples, result));
+        }
+
+        let mut search_state = *make_unmaker.state;
+        let search_ctx = &mut SearchContext::with_evaluator(
+            &mut search_state,
+            transpos,
+            None,
+            evaluator.clone(),
+        );
+        search_ctx.make_unmaker.hash_history = make_unmaker.hash_history.clone();
+        let (score, pv) = match options.limit {
+            Limit::Depth(depth) => search_ctx.iterative_deepen_to_depth(depth),
+            Limit::Nodes(nodes) => search_ctx.iterative_deepen_to_nodes(nodes),
+        };
+        let Some(&best_move) = pv.first() else {
+            return Ok((samples, 0.5));
+        };
+
+        let in_check = move_generator.is_check(make_unmaker.state);
+        if is_kept(options, in_check, best_move, score) {
+            let white = make_unmaker.state.flags.active_color() == Color::White;
+            samples.push(Sample {
+                fen: make_unmaker.state.to_fen(),
+                score: if white { score } else { -score },
+                best_move,
+            });
+        }
+        make_unmaker.make_move(best_move);
+    }
+    Ok((samples, 0.5))
+}
+
+/// Result for white if the game is over
+fn game_result(move_generator: &MoveGenerator, make_unmaker: &mut MakeUnmaker) -> Option<f64> {
+    if legal_moves(move_generator, make_unmaker).is_empty() {
+        return Some(if !move_generator.is_check(make_unmaker.state) {
+            0.5
+        } else if make_unmaker.state.flags.active_color() == Color::White {
+            0.0
+        } else {
+            1.0
+        });
+    }
+    if make_unmaker.state.halfmove >= 100
+        || make_unmaker.is_repetition()
+        || is_insufficient_material(make_unmaker.state)
+    {
+        return Some(0.5);
+    }
+    None
+}
+
+/// Whether a position searched to `score` and `best_move` is quiet enough to be written
+fn is_kept(options: &Options, in_check: bool, best_move: Move, score: i32) -> bool {
+    (options.keep_checks || !in_check)
+        && (options.keep_captures || !best_move.code().is_capture())
+        && mate_distance(score).is_none()
+}
+
+/// Position after a random opening and random moves, with its hash history, from which the
+/// game is not over
+fn random_opening(
+    options: &Options,
+    random: &mut Random,
+    move_generator: &MoveGenerator,
+) -> Result<(GameState, Vec<u64>), String> {
+    for _ in 0..MAX_OPENING_ATTEMPTS {
+        let opening = &options.openings[random.below(options.openings.len())];
+        let mut state = GameState::from_fen(opening.clone());
+        let make_unmaker = &mut MakeUnmaker::new(&mut state);
+        let mut playable = true;
+        for ply in 0..=options.random_plies {
+            let moves = legal_moves(move_generator, make_unmaker);
+            if moves.is_empty() {
+                playable = false;
+                break;
+            }
+            if ply < options.random_plies {
+                make_unmaker.make_move(moves[random.below(moves.len())]);
+            }
+        }
+        if playable {
+            let history = std::mem::take(&mut make_unmaker.hash_history);
+            return Ok((state, history));
+        }
+    }
+    Err(format!(
+        "No game lasts {} random plies from the openings",
+        options.random_plies
+    ))
+}
+
+fn legal_moves(move_generator: &MoveGenerator, make_unmaker: &mut MakeUnmaker) -> Vec<Move> {
+    let mut moves = Vec::new();
+    move_generator.get_pseudo_legal_moves(make_unmaker.state, &mut moves);
+    moves.retain(|&m| {
+        make_unmaker.make_move(m);
+        let legal = move_generator.was_move_legal(make_unmaker.state);
+        make_unmaker.unmake_move(m);
+        legal
+    });
+    moves
+}
+
+/// Neither side can mate: kings and at most one minor piece
+fn is_insufficient_material(state: &GameState) -> bool {
+    let (white, black) = (&state.boards.white, &state.boards.black);
+    let heavy = white.pawn | white.rook | white.queen | black.pawn | black.rook | black.queen;
+    let minors = white.knight | white.bishop | black.knight | black.bishop;
+    heavy.is_empty() && minors.count_ones() <= 1
+}
+
+fn write_samples(output: &mut impl Write, samples: &[Sample], result: f64) {
+    for sample in samples {
+        writeln!(
+            output,
+            "{} | {} | {} | {:.1}",
+            sample.fen,
+            sample.score,
+            sample.best_move.to_string().to_lowercase(),
+            result
+        )
+        .expect("Failed to write the output");
+    }
+}
+
+/// Xorshift generator, games only need to differ from each other
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Random(seed.max(1))
+    }
+
+    /// Uniform enough below `n` for small `n`
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chess_core::{r#move::MoveCode, square::Square};
+    use chess_engines::alpha_beta::search::MATE_SCORE;
+
+    use super::*;
+
+    fn parse_options(args: &[&str]) -> Options {
+        parse_args(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn test_write_samples() {
+        let samples = [
+            Sample {
+                fen: START_FEN.to_string(),
+                score: 25,
+                best_move: Move::new(Square(12), Square(28), MoveCode::DoublePawnPush),
+            },
+            Sample {
+                fen: "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1".to_string(),
+                score: -30,
+                best_move: Move::new(Square(49), Square(57), MoveCode::QueenPromotion),
+            },
+        ];
+        let output = &mut Vec::new();
+        write_samples(output, &samples, 0.5);
+        assert_eq!(
+            String::from_utf8(output.clone()).unwrap(),
+            format!(
+                "{} | 25 | e2e4 | 0.5\n4k3/1P6/8/8/8/8/8/4K3 w - - 0 1 | -30 | b7b8q | 0.5\n",
+                START_FEN
+            )
+        );
+    }
+
+    #[test]
+    fn test_is_kept() {
+        let quiet = Move::new(Square(12), Square(28), MoveCode::DoublePawnPush);
+        let capture = Move::new(Square(28), Square(35), MoveCode::Capture);
+        let options = &parse_options(&["out.txt"]);
+        assert!(is_kept(options, false, quiet, 40));
+        assert!(!is_kept(options, true, quiet, 40));
+        assert!(!is_kept(options, false, capture, 40));
+        assert!(!is_kept(options, false, quiet, MATE_SCORE - 3));
+        assert!(!is_kept(options, false, quiet, -MATE_SCORE + 4));
+
+        let options = &parse_options(&["out.txt", "--keep-checks", "--keep-captures"]);
+        assert!(is_kept(options, true, capture, 40));
+        assert!(!is_kept(options, true, capture, MATE_SCORE - 3));
+    }
+
+    #[test]
+    fn test_game_result() {
+        let move_generator = &MoveGenerator::new();
+        for (fen, result) in [
+            (START_FEN, None),
+            // White is mated
+            ("4k3/8/8/8/8/8/5PPP/3r2K1 w - - 0 1", Some(0.0)),
+            // Black is mated
+            ("3R2k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", Some(1.0)),
+            // Stalemate
+            ("k7/8/1Q6/8/8/8/8/7K b - - 0 1", Some(0.5)),
+            // Fifty moves
+            ("k7/8/8/8/8/8/8/KQ6 w - - 100 80", Some(0.5)),
+            // Insufficient material
+            ("k7/8/8/8/8/8/8/KN6 w - - 0 1", Some(0.5)),
+        ] {
+            let state = &mut GameState::from_fen(fen.to_string());
+            let make_unmaker = &mut MakeUnmaker::new(state);
+            assert_eq!(game_result(move_generator, make_unmaker), result, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_random_opening() {
+        let move_generator = &MoveGenerator::new();
+        let random = &mut Random::new(1);
+        let (state, history) =
+            random_opening(&parse_options(&["out.txt"]), random, move_generator).unwrap();
+        assert_eq!(history.len(), 8);
+        assert_ne!(state, GameState::from_fen(START_FEN.to_string()));
+
+        // The only opening is a stalemate
+        let options = &mut parse_options(&["out.txt"]);
+        options.openings = vec!["k7/8/1Q6/8/8/8/8/7K b - - 0 1".to_string()];
+        assert!(random_opening(options, random, move_generator).is_err());
+    }
+}
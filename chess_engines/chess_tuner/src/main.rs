@@ -0,0 +1,257 @@
+use std::{fs, thread};
+
+use chess_core::{color::Color, r#move::MoveGenerator, state::game_state::GameState};
+use chess_engines::alpha_beta::evaluation::{EvalParams, Evaluator, SimpleEvaluator};
+
+// Texel tuning of the evaluation parameters
+//
+// Usage: chess_tuner <positions> [--output path] [--iterations n] [--threads n]
+//
+// Each line of the positions file is a quiet position with the result of the game it was taken
+// from, either as EPD with a result opcode (`c9 "1-0";`), with the result in brackets
+// (`<fen> [0.5]`) or in our own format (`<fen>;1.0`). Results are from the point of view of
+// white.
+//
+// The tuner minimizes the mean squared difference between the results and the sigmoid of the
+// static evaluation, changing every parameter by one centipawn at a time until no change lowers
+// the error. The tuned parameters are written after each pass, as a TOML or JSON parameter file
+// depending on the extension of the output, or else as the source of the `default_params`
+// module.
+
+struct Position {
+    state: GameState,
+    /// 1 for a white win, 0.5 for a draw and 0 for a black win
+    result: f64,
+}
+
+struct Options {
+    positions: String,
+    output: String,
+    iterations: usize,
+    threads: usize,
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1).collect());
+    let positions = load_positions(&options.positions);
+    println!("Loaded {} positions", positions.len());
+
+    let mut params = EvalParams::default();
+    let k = fit_k(&positions, &params, options.threads);
+    println!("K = {:.4}", k);
+
+    let mut best_error = error(&positions, &params, k, options.threads);
+    println!("Initial error: {:.6}", best_error);
+    for iteration in 1..=options.iterations {
+        let mut improved = 0;
+        for index in 0..params.values_mut().count() {
+            for delta in [1, -1] {
+                *params.values_mut().nth(index).unwrap() += delta;
+                let new_error = error(&positions, &params, k, options.threads);
+                if new_error < best_error {
+                    best_error = new_error;
+                    improved += 1;
+                    break;
+                }
+                *params.values_mut().nth(index).unwrap() -= delta;
+            }
+        }
+        println!(
+            "Pass {}: error {:.6}, {} parameters changed",
+            iteration, best_error, improved
+        );
+        write_params(&params, &options.output);
+        if improved == 0 {
+            break;
+        }
+    }
+    println!("Parameters written to {}", options.output);
+}
+
+fn write_params(params: &EvalParams, path: &str) {
+    let contents = match path.rsplit_once('.') {
+        Some((_, "toml")) => params.to_toml(),
+        Some((_, "json")) => params.to_json(),
+        _ => params.to_rust_source(),
+    };
+    fs::write(path, contents).expect("Failed to write the output");
+}
+
+fn parse_args(args: Vec<String>) -> Options {
+    let usage = "Usage: chess_tuner <positions> [--output path] [--iterations n] [--threads n]";
+    let mut options = Options {
+        positions: String::new(),
+        output: "default_params.rs".to_string(),
+        iterations: 100,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => options.output = args.next().expect(usage),
+            "--iterations" => options.iterations = args.next().expect(usage).parse().expect(usage),
+            "--threads" => {
+                options.threads = args
+                    .next()
+                    .and_then(|threads| threads.parse().ok())
+                    .filter(|&threads| threads > 0)
+                    .expect(usage)
+            }
+            _ if options.positions.is_empty() => options.positions = arg,
+            _ => panic!("{}", usage),
+        }
+    }
+    if options.positions.is_empty() {
+        panic!("{}", usage);
+    }
+    options
+}
+
+fn load_positions(path: &str) -> Vec<Position> {
+    let move_generator = MoveGenerator::new();
+    let mut skipped = 0;
+    let positions = fs::read_to_string(path)
+        .expect("Failed to read the positions")
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let position = parse_position(line);
+            skipped += position.is_none() as usize;
+            position
+        })
+        // Positions in check are not quiet
+        .filter(|position| !move_generator.is_check(&position.state))
+        .collect();
+    if skipped > 0 {
+        println!("Skipped {} invalid lines", skipped);
+    }
+    positions
+}
+
+/// Position and result of a line, the result is the last token of the line. None if the FEN or
+/// the result is invalid.
+fn parse_position(line: &str) -> Option<Position> {
+    let fields: Vec<_> = line.split_whitespace().take(4).collect();
+    if fields.len() < 4 {
+        return None;
+    }
+    let result = line
+        .split(|c: char| c.is_whitespace() || "\";[]".contains(c))
+        .rfind(|token| !token.is_empty())?;
+    let result = match result {
+        "1-0" | "1.0" => 1.0,
+        "1/2-1/2" | "0.5" => 0.5,
+        "0-1" | "0.0" => 0.0,
+        _ => return None,
+    };
+    Some(Position {
+        state: GameState::try_from_fen(fields.join(" ")).ok()?,
+        result,
+    })
+}
+
+/// Expected result for white of a position evaluated at `score` centipawns for white
+fn sigmoid(score: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score as f64 / 400.0))
+}
+
+/// Mean squared difference between the results and the expected results
+fn error(positions: &[Position], params: &EvalParams, k: f64, threads: usize) -> f64 {
+    let chunk_size = positions.len().div_ceil(threads).max(1);
+    let total: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut evaluator = SimpleEvaluator::with_params(params.clone());
+                    chunk
+                        .iter()
+                        .map(|position| {
+                            let score = match position.state.flags.active_color() {
+                                Color::White => evaluator.evaluate(&position.state),
+                                Color::Black => -evaluator.evaluate(&position.state),
+                            };
+                            (position.result - sigmoid(score, k)).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum()
+    });
+    total / positions.len() as f64
+}
+
+/// Scaling constant of the sigmoid which best fits the untuned evaluation, found by ternary search
+fn fit_k(positions: &[Position], params: &EvalParams, threads: usize) -> f64 {
+    let (mut low, mut high) = (0.0, 3.0);
+    for _ in 0..40 {
+        let left = low + (high - low) / 3.0;
+        let right = high - (high - low) / 3.0;
+        if error(positions, params, left, threads) < error(positions, params, right, threads) {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+    (low + high) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_position() {
+        let epd = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3";
+        let fen = format!("{} 0 1", epd);
+        for (line, result) in [
+            (format!("{} c9 \"1-0\";", epd), 1.0),
+            (format!("{} [0.5]", fen), 0.5),
+            (format!("{};0.0", fen), 0.0),
+        ] {
+            let position = parse_position(&line).unwrap();
+            assert_eq!(position.result, result, "{}", line);
+            assert_eq!(position.state, GameState::from_fen(fen.clone()));
+        }
+
+        // Invalid FEN
+        assert!(
+            parse_position("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN w KQkq - [1.0]").is_none()
+        );
+        assert!(parse_position("8/8/8/8/8/8/8/8 w - - [1.0]").is_none());
+        // Invalid result
+        assert!(parse_position(&format!("{} [1]", fen)).is_none());
+        assert!(parse_position(&format!("{} c9 \"*\";", fen)).is_none());
+        assert!(parse_position("1-0").is_none());
+    }
+
+    #[test]
+    fn test_error() {
+        let positions: Vec<_> = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - [0.5]",
+            // White is a queen up
+            "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - [1.0]",
+            // Black is a queen up
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR b KQkq - [0.0]",
+        ]
+        .into_iter()
+        .map(|line| parse_position(line).unwrap())
+        .collect();
+        let params = &EvalParams::default();
+
+        // With K = 0 every position is expected to be a draw
+        let expected = (0.0 + 0.25 + 0.25) / 3.0;
+        assert!((error(&positions, params, 0.0, 1) - expected).abs() < 1e-9);
+        // More threads than positions
+        assert!((error(&positions, params, 0.0, 8) - expected).abs() < 1e-9);
+
+        // The results follow the evaluation, the larger K the lower the error
+        let k = fit_k(&positions, params, 2);
+        assert!(k > 2.9, "{}", k);
+        assert!(error(&positions, params, k, 1) < error(&positions, params, 1.0, 1));
+    }
+}
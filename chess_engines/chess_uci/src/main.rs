@@ -0,0 +1,540 @@
+use std::{
+    io::{BufRead, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use chess_core::{
+    color::Color,
+    r#move::{Move, MoveGenerator},
+    state::{game_state::GameState, make_unmake::MakeUnmaker},
+};
+use chess_engines::alpha_beta::{
+    evaluation::{EvalParams, Evaluator, NnueEvaluator, SimpleEvaluator},
+    opening_book::{BookSelection, DEFAULT_BOOK_DEPTH, OpeningBook, game_ply},
+    search::{SearchContext, SearchInfo, mate_distance},
+    search_limits::{MAX_DEPTH, SearchLimits},
+    search_stats::SearchStats,
+    skill::{FULL_STRENGTH_ELO, MAX_LEVEL, MIN_ELO, Skill},
+    transposition_table::{Bound, DEFAULT_SIZE_MB, TranspositionTable},
+};
+use chrono::Duration;
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const MAX_HASH_MB: usize = 4096;
+const MAX_THREADS: usize = 256;
+const MAX_CONTEMPT: i32 = 200;
+const MAX_MULTI_PV: usize = 256;
+const MAX_BOOK_DEPTH: usize = 100;
+/// Left on the clock for the time the move takes to reach the GUI, in milliseconds
+const MOVE_OVERHEAD: i64 = 50;
+
+fn main() {
+    let mut uci = Uci::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.unwrap();
+        if !uci.handle_command(&line) {
+            return;
+        }
+        std::io::stdout().flush().unwrap();
+    }
+    // End of the input, same as quit
+    uci.stop_search();
+}
+
+struct Uci {
+    state: GameState,
+    /// Hashes of the positions before the moves of the position command, for repetitions
+    history: Vec<u64>,
+    /// Plies played in the game, from the fullmove number of the FEN and the moves
+    game_ply: usize,
+    /// Shared with the search thread
+    transpos: Arc<TranspositionTable>,
+    threads: usize,
+    contempt: i32,
+    /// Number of best lines reported
+    multi_pv: usize,
+    skill_level: u8,
+    /// Set by UCI_LimitStrength, the strength then comes from the Elo instead of the level
+    limit_strength: bool,
+    elo: u32,
+    /// Set by OwnBook, book moves are then played without searching
+    own_book: bool,
+    /// The built-in book unless BookFile is set
+    book: OpeningBook,
+    move_generator: MoveGenerator,
+    evaluator: SimpleEvaluator,
+    /// Used instead of the simple evaluation once a network is loaded
+    nnue: Option<NnueEvaluator>,
+    /// Set by debug on, the statistics are then printed after each search
+    debug: bool,
+    /// Statistics of the last search
+    stats: SearchStats,
+    /// Search started by go, returning its statistics
+    search: Option<JoinHandle<SearchStats>>,
+    /// Set by stop and quit
+    stop: Arc<AtomicBool>,
+}
+
+impl Uci {
+    fn new() -> Self {
+        Uci {
+            state: GameState::from_fen(START_FEN.to_string()),
+            history: Vec::new(),
+            game_ply: 0,
+            transpos: Arc::new(TranspositionTable::new(DEFAULT_SIZE_MB)),
+            threads: 1,
+            contempt: 0,
+            multi_pv: 1,
+            skill_level: MAX_LEVEL,
+            limit_strength: false,
+            elo: FULL_STRENGTH_ELO,
+            own_book: false,
+            book: OpeningBook::builtin(),
+            move_generator: MoveGenerator::new(),
+            evaluator: SimpleEvaluator::default(),
+            nnue: None,
+            debug: false,
+            stats: SearchStats::default(),
+            search: None,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns false when the engine should exit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+        let command = tokens.next();
+        // The other commands wait for the search to end
+        if !matches!(command, Some("isready" | "stop" | "quit")) {
+            self.wait_search();
+        }
+        match command {
+            Some("uci") => {
+                println!("id name chess-engine");
+                println!("id author matthiasgreen");
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_SIZE_MB, MAX_HASH_MB
+                );
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!(
+                    "option name Contempt type spin default 0 min {} max {}",
+                    -MAX_CONTEMPT, MAX_CONTEMPT
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                );
+                println!(
+                    "option name Skill Level type spin default {} min 0 max {}",
+                    MAX_LEVEL, MAX_LEVEL
+                );
+                println!("option name UCI_LimitStrength type check default false");
+                println!(
+                    "option name UCI_Elo type spin default {} min {} max {}",
+                    FULL_STRENGTH_ELO, MIN_ELO, FULL_STRENGTH_ELO
+                );
+                println!("option name OwnBook type check default false");
+                println!("option name BookFile type string default <empty>");
+                println!(
+                    "option name BookDepth type spin default {} min 0 max {}",
+                    DEFAULT_BOOK_DEPTH, MAX_BOOK_DEPTH
+                );
+                println!("option name BookBestMove type check default false");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name NnueFile type string default <empty>");
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("debug") => self.debug = tokens.next() == Some("on"),
+            Some("setoption") => self.set_option(tokens.collect()),
+            Some("ucinewgame") => {
+                self.state = GameState::from_fen(START_FEN.to_string());
+                self.history.clear();
+                self.game_ply = 0;
+                self.transpos_mut().clear();
+            }
+            Some("position") => self.set_position(tokens.collect()),
+            Some("go") => self.go(tokens.collect()),
+            // Not part of the protocol, breakdown of the static evaluation of the position
+            Some("eval") => self.print_eval(),
+            // Not part of the protocol either, statistics of the last search
+            Some("stats") => print_info_string(&self.stats.to_string()),
+            Some("stop") => self.stop_search(),
+            Some("quit") => {
+                self.stop_search();
+                return false;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// setoption name <name> value <value>, names can have several words
+    fn set_option(&mut self, tokens: Vec<&str>) {
+        let Some(value_index) = tokens.iter().position(|&token| token == "value") else {
+            return;
+        };
+        let (name, value) = match (&tokens[..value_index], &tokens[value_index + 1..]) {
+            (["name", name @ ..], value) => (name.join(" "), value.join(" ")),
+            _ => return,
+        };
+        let name = name.as_str();
+        if name.eq_ignore_ascii_case("EvalFile") {
+            self.set_eval_file(&value);
+            return;
+        }
+        if name.eq_ignore_ascii_case("NnueFile") {
+            self.set_nnue_file(&value);
+            return;
+        }
+        if name.eq_ignore_ascii_case("BookFile") {
+            self.set_book_file(&value);
+            return;
+        }
+        if name.eq_ignore_ascii_case("UCI_LimitStrength") {
+            self.limit_strength = value.eq_ignore_ascii_case("true");
+            return;
+        }
+        if name.eq_ignore_ascii_case("OwnBook") {
+            self.own_book = value.eq_ignore_ascii_case("true");
+            return;
+        }
+        if name.eq_ignore_ascii_case("BookBestMove") {
+            self.book.selection = match value.eq_ignore_ascii_case("true") {
+                true => BookSelection::Best,
+                false => BookSelection::Weighted,
+            };
+            return;
+        }
+        let Ok(value) = value.parse::<i64>() else {
+            return;
+        };
+        if name.eq_ignore_ascii_case("Hash") {
+            self.transpos_mut()
+                .resize(value.clamp(1, MAX_HASH_MB as i64) as usize);
+        } else if name.eq_ignore_ascii_case("Threads") {
+            self.threads = value.clamp(1, MAX_THREADS as i64) as usize;
+        } else if name.eq_ignore_ascii_case("Contempt") {
+            self.contempt = value.clamp(-MAX_CONTEMPT as i64, MAX_CONTEMPT as i64) as i32;
+        } else if name.eq_ignore_ascii_case("MultiPV") {
+            self.multi_pv = value.clamp(1, MAX_MULTI_PV as i64) as usize;
+        } else if name.eq_ignore_ascii_case("Skill Level") {
+            self.skill_level = value.clamp(0, MAX_LEVEL as i64) as u8;
+        } else if name.eq_ignore_ascii_case("UCI_Elo") {
+            self.elo = value.clamp(MIN_ELO as i64, FULL_STRENGTH_ELO as i64) as u32;
+        } else if name.eq_ignore_ascii_case("BookDepth") {
+            self.book.max_depth = value.clamp(0, MAX_BOOK_DEPTH as i64) as usize;
+        }
+    }
+
+    /// Evaluation parameters from a TOML or JSON file, the built-in ones when empty
+    fn set_eval_file(&mut self, path: &str) {
+        let params = match path {
+            "" | "<empty>" => EvalParams::default(),
+            path => match EvalParams::load(path) {
+                Ok(params) => params,
+                Err(error) => {
+                    // The previous parameters stay in use
+                    print_info_string(&error);
+                    return;
+                }
+            },
+        };
+        self.evaluator = SimpleEvaluator::with_params(params);
+    }
+
+    /// Opening book file, the built-in book when empty. The selection and depth are kept.
+    fn set_book_file(&mut self, path: &str) {
+        let mut book = match path {
+            "" | "<empty>" => OpeningBook::builtin(),
+            path => match OpeningBook::load(path) {
+                Ok(book) => book,
+                Err(error) => {
+                    // The previous book stays in use
+                    print_info_string(&error);
+                    return;
+                }
+            },
+        };
+        book.selection = self.book.selection;
+        book.max_depth = self.book.max_depth;
+        self.book = book;
+    }
+
+    /// NNUE network file, the simple evaluation is used when empty
+    fn set_nnue_file(&mut self, path: &str) {
+        self.nnue = match path {
+            "" | "<empty>" => None,
+            path => match NnueEvaluator::load(path) {
+                Ok(nnue) => Some(nnue),
+                Err(error) => {
+                    // The previous network stays in use
+                    print_info_string(&error);
+                    return;
+                }
+            },
+        };
+    }
+
+    fn print_eval(&self) {
+        println!("{}", self.evaluator.eval_trace(&self.state));
+        if let Some(nnue) = &self.nnue {
+            let mut nnue = nnue.clone();
+            nnue.reset(&self.state);
+            let score = match self.state.flags.active_color() {
+                Color::White => nnue.evaluate(&self.state),
+                Color::Black => -nnue.evaluate(&self.state),
+            };
+            println!("NNUE score: {} (white side)", score);
+        }
+    }
+
+    /// position [startpos | fen <fen>] [moves <move>...]
+    fn set_position(&mut self, tokens: Vec<&str>) {
+        let moves_index = tokens.iter().position(|&t| t == "moves");
+        let (position, moves) = match moves_index {
+            Some(i) => (&tokens[..i], &tokens[i + 1..]),
+            None => (&tokens[..], &[][..]),
+        };
+        let fen = match position {
+            ["startpos"] => START_FEN.to_string(),
+            ["fen", fen @ ..] => fen.join(" "),
+            _ => return,
+        };
+
+        let game_ply = game_ply(&fen);
+        self.state = match GameState::try_from_fen(fen) {
+            Ok(state) => state,
+            Err(error) => {
+                // The previous position stays in use
+                print_info_string(&error);
+                return;
+            }
+        };
+        let make_unmaker = &mut MakeUnmaker::new(&mut self.state);
+        for m in moves {
+            let Some(m) = find_move(&self.move_generator, make_unmaker.state, m) else {
+                break;
+            };
+            make_unmaker.make_move(m);
+        }
+        self.history = make_unmaker.hash_history.clone();
+        self.game_ply = game_ply + self.history.len();
+    }
+
+    /// go [wtime <ms>] [btime <ms>] [winc <ms>] [binc <ms>] [movetime <ms>] [depth <n>]
+    /// [nodes <n>] [mate <n>] [infinite] [searchmoves <move>...]
+    ///
+    /// The search runs on its own thread, which sends the best move once it ends
+    fn go(&mut self, tokens: Vec<&str>) {
+        let mut params = std::collections::HashMap::new();
+        for pair in tokens.windows(2) {
+            if let Ok(value) = pair[1].parse::<i64>() {
+                params.insert(pair[0], value);
+            }
+        }
+        let white = self.state.flags.active_color() == Color::White;
+        let (time, inc) = if white {
+            (params.get("wtime"), params.get("winc"))
+        } else {
+            (params.get("btime"), params.get("binc"))
+        };
+        let move_time = match (params.get("movetime"), time) {
+            (Some(&movetime), _) => Some(movetime),
+            (None, Some(&time)) => {
+                Some((time / 30 + inc.copied().unwrap_or(0) / 2).min(time - MOVE_OVERHEAD))
+            }
+            (None, None) => None,
+        };
+        // The moves follow searchmoves until the next parameter
+        let search_moves = tokens
+            .iter()
+            .skip_while(|&&token| token != "searchmoves")
+            .skip(1)
+            .map_while(|token| find_move(&self.move_generator, &self.state, token))
+            .collect();
+        let infinite = tokens.contains(&"infinite");
+
+        let mut limits = SearchLimits {
+            depth: params
+                .get("depth")
+                .map(|&depth| depth.clamp(1, MAX_DEPTH as i64) as u8),
+            nodes: params.get("nodes").map(|&nodes| nodes.max(1) as u64),
+            mate: params.get("mate").map(|&moves| moves.clamp(1, 100) as u32),
+            move_time: move_time.map(|time| Duration::milliseconds(time.max(1))),
+            search_moves,
+        };
+        // A second is spent on the move when nothing else limits the search
+        if !infinite
+            && limits.depth.is_none()
+            && limits.nodes.is_none()
+            && limits.mate.is_none()
+            && limits.move_time.is_none()
+        {
+            limits.move_time = Some(Duration::seconds(1));
+        }
+
+        if self.own_book
+            && !infinite
+            && limits.search_moves.is_empty()
+            && let Some(m) = self.book.probe(&self.state, self.game_ply)
+        {
+            println!("bestmove {}", uci_move_string(m));
+            return;
+        }
+
+        self.stop.store(false, Ordering::Relaxed);
+        let search = Search {
+            state: self.state,
+            history: self.history.clone(),
+            transpos: Arc::clone(&self.transpos),
+            threads: self.threads,
+            contempt: self.contempt,
+            multi_pv: self.multi_pv,
+            skill: if self.limit_strength {
+                Skill::from_elo(self.elo)
+            } else {
+                Skill::new(self.skill_level)
+            },
+            debug: self.debug,
+            infinite,
+            stop: Arc::clone(&self.stop),
+        };
+        self.search = Some(match &self.nnue {
+            Some(nnue) => {
+                let nnue = nnue.clone();
+                thread::spawn(move || search.run(nnue, &limits))
+            }
+            None => {
+                let evaluator = self.evaluator.clone();
+                thread::spawn(move || search.run(evaluator, &limits))
+            }
+        });
+    }
+
+    /// Waits for the search started by go, if any, and keeps its statistics
+    fn wait_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.stats = search.join().unwrap();
+        }
+    }
+
+    /// Stops the search started by go, if any, which then sends its best move
+    fn stop_search(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(search) = &self.search {
+            search.thread().unpark();
+        }
+        self.wait_search();
+    }
+
+    /// Only called between searches, the search thread holds the other reference
+    fn transpos_mut(&mut self) -> &mut TranspositionTable {
+        Arc::get_mut(&mut self.transpos).expect("no search is running")
+    }
+}
+
+/// What a search started by go needs, moved to its thread
+struct Search {
+    state: GameState,
+    history: Vec<u64>,
+    transpos: Arc<TranspositionTable>,
+    threads: usize,
+    contempt: i32,
+    multi_pv: usize,
+    skill: Skill,
+    debug: bool,
+    /// The best move is only sent after stop
+    infinite: bool,
+    stop: Arc<AtomicBool>,
+}
+
+impl Search {
+    /// Searches the position, sends the best move and returns the statistics
+    fn run<E: Evaluator>(mut self, evaluator: E, limits: &SearchLimits) -> SearchStats {
+        let search_ctx =
+            &mut SearchContext::with_evaluator(&mut self.state, &self.transpos, None, evaluator);
+        search_ctx.threads = self.threads;
+        search_ctx.params.contempt = self.contempt;
+        search_ctx.multi_pv = self.multi_pv;
+        search_ctx.make_unmaker.hash_history = self.history;
+        search_ctx.stop = Some(&self.stop);
+        search_ctx.on_info = Some(Box::new(print_info));
+        let pv = self.skill.search(search_ctx, limits).pv;
+        let stats = std::mem::take(&mut search_ctx.stats);
+
+        if self.debug {
+            print_info_string(&stats.to_string());
+        }
+        while self.infinite && !self.stop.load(Ordering::Relaxed) {
+            thread::park();
+        }
+        match pv.first() {
+            Some(m) => println!("bestmove {}", uci_move_string(*m)),
+            None => println!("bestmove 0000"),
+        }
+        stats
+    }
+}
+
+/// Errors and statistics are sent as info strings, one per line
+fn print_info_string(text: &str) {
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        println!("info string {}", line);
+    }
+}
+
+fn print_info(info: &SearchInfo) {
+    let bound = match info.bound {
+        Bound::Exact => "",
+        Bound::Lower => " lowerbound",
+        Bound::Upper => " upperbound",
+    };
+    let pv = info
+        .pv
+        .iter()
+        .map(|m| uci_move_string(*m))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let score = match mate_distance(info.score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", info.score),
+    };
+    println!(
+        "info depth {} multipv {} score {}{} nodes {} time {} hashfull {} pv {}",
+        info.depth,
+        info.multi_pv,
+        score,
+        bound,
+        info.nodes,
+        info.time.num_milliseconds(),
+        info.hashfull,
+        pv
+    );
+}
+
+/// UCI promotions are lower case (e7e8q)
+fn uci_move_string(m: Move) -> String {
+    m.to_string().to_lowercase()
+}
+
+/// Legal move of `state` written `string` in UCI notation
+fn find_move(move_generator: &MoveGenerator, state: &GameState, string: &str) -> Option<Move> {
+    let mut moves = Vec::new();
+    move_generator.get_pseudo_legal_moves(state, &mut moves);
+    moves.into_iter().find(|&m| {
+        uci_move_string(m) == string && {
+            let mut state = *state;
+            MakeUnmaker::new(&mut state).make_move(m);
+            move_generator.was_move_legal(&state)
+        }
+    })
+}
@@ -0,0 +1,185 @@
+// Evaluation parameters
+// Written by chess_tuner, which overwrites this file with the tuned weights.
+
+use super::{params::EvalParams, phase_score::PhaseScore};
+
+#[rustfmt::skip]
+pub(super) const DEFAULT_PARAMS: EvalParams = EvalParams {
+    piece_values: [100, 300, 300, 500, 900, 0],
+    midgame_tables: [
+        // Pawn
+        [
+               0,    0,    0,    0,    0,    0,    0,    0,
+               5,   10,   10,  -20,  -20,   10,   10,    5,
+               5,   -5,  -10,    0,    0,  -10,   -5,    5,
+               0,    0,    0,   20,   20,    0,    0,    0,
+               5,    5,   10,   25,   25,   10,    5,    5,
+              10,   10,   20,   30,   30,   20,   10,   10,
+              50,   50,   50,   50,   50,   50,   50,   50,
+               0,    0,    0,    0,    0,    0,    0,    0,
+        ],
+        // Knight
+        [
+             -50,  -40,  -30,  -30,  -30,  -30,  -40,  -50,
+             -40,  -20,    0,    5,    5,    0,  -20,  -40,
+             -30,    5,   10,   15,   15,   10,    5,  -30,
+             -30,    0,   15,   20,   20,   15,    0,  -30,
+             -30,    5,   15,   20,   20,   15,    5,  -30,
+             -30,    0,   10,   15,   15,   10,    0,  -30,
+             -40,  -20,    0,    0,    0,    0,  -20,  -40,
+             -50,  -40,  -30,  -30,  -30,  -30,  -40,  -50,
+        ],
+        // Bishop
+        [
+             -20,  -10,  -10,  -10,  -10,  -10,  -10,  -20,
+             -10,    5,    0,    0,    0,    0,    5,  -10,
+             -10,   10,   10,   10,   10,   10,   10,  -10,
+             -10,    0,   10,   10,   10,   10,    0,  -10,
+             -10,    5,    5,   10,   10,    5,    5,  -10,
+             -10,    0,    5,   10,   10,    5,    0,  -10,
+             -10,    0,    0,    0,    0,    0,    0,  -10,
+             -20,  -10,  -10,  -10,  -10,  -10,  -10,  -20,
+        ],
+        // Rook
+        [
+               0,    0,    0,    5,    5,    0,    0,    0,
+              -5,    0,    0,    0,    0,    0,    0,   -5,
+              -5,    0,    0,    0,    0,    0,    0,   -5,
+              -5,    0,    0,    0,    0,    0,    0,   -5,
+              -5,    0,    0,    0,    0,    0,    0,   -5,
+              -5,    0,    0,    0,    0,    0,    0,   -5,
+               5,   10,   10,   10,   10,   10,   10,    5,
+               0,    0,    0,    0,    0,    0,    0,    0,
+        ],
+        // Queen
+        [
+             -20,  -10,  -10,   -5,   -5,  -10,  -10,  -20,
+             -10,    0,    5,    0,    0,    0,    0,  -10,
+             -10,    5,    5,    5,    5,    5,    0,  -10,
+               0,    0,    5,    5,    5,    5,    0,   -5,
+              -5,    0,    5,    5,    5,    5,    0,   -5,
+             -10,    0,    5,    5,    5,    5,    0,  -10,
+             -10,    0,    0,    0,    0,    0,    0,  -10,
+             -20,  -10,  -10,   -5,   -5,  -10,  -10,  -20,
+        ],
+        // King
+        [
+              20,   30,   10,    0,    0,   10,   30,   20,
+              20,   20,    0,    0,    0,    0,   20,   20,
+             -10,  -20,  -20,  -20,  -20,  -20,  -20,  -10,
+             -20,  -30,  -30,  -40,  -40,  -30,  -30,  -20,
+             -30,  -40,  -40,  -50,  -50,  -40,  -40,  -30,
+             -30,  -40,  -40,  -50,  -50,  -40,  -40,  -30,
+             -30,  -40,  -40,  -50,  -50,  -40,  -40,  -30,
+             -30,  -40,  -40,  -50,  -50,  -40,  -40,  -30,
+        ],
+    ],
+    endgame_tables: [
+        // Pawn
+        [
+               0,    0,    0,    0,    0,    0,    0,    0,
+               0,    0,    0,    0,    0,    0,    0,    0,
+               5,    5,    5,    5,    5,    5,    5,    5,
+              15,   15,   15,   15,   15,   15,   15,   15,
+              30,   30,   30,   30,   30,   30,   30,   30,
+              50,   50,   50,   50,   50,   50,   50,   50,
+              80,   80,   80,   80,   80,   80,   80,   80,
+               0,    0,    0,    0,    0,    0,    0,    0,
+        ],
+        // Knight
+        [
+             -50,  -40,  -30,  -30,  -30,  -30,  -40,  -50,
+             -40,  -20,    0,    0,    0,    0,  -20,  -40,
+             -30,    0,   10,   15,   15,   10,    0,  -30,
+             -30,    0,   15,   20,   20,   15,    0,  -30,
+             -30,    0,   15,   20,   20,   15,    0,  -30,
+             -30,    0,   10,   15,   15,   10,    0,  -30,
+             -40,  -20,    0,    0,    0,    0,  -20,  -40,
+             -50,  -40,  -30,  -30,  -30,  -30,  -40,  -50,
+        ],
+        // Bishop
+        [
+             -20,  -10,  -10,  -10,  -10,  -10,  -10,  -20,
+             -10,    0,    0,    0,    0,    0,    0,  -10,
+             -10,    0,    5,    5,    5,    5,    0,  -10,
+             -10,    0,    5,   10,   10,    5,    0,  -10,
+             -10,    0,    5,   10,   10,    5,    0,  -10,
+             -10,    0,    5,    5,    5,    5,    0,  -10,
+             -10,    0,    0,    0,    0,    0,    0,  -10,
+             -20,  -10,  -10,  -10,  -10,  -10,  -10,  -20,
+        ],
+        // Rook
+        [
+               0,    0,    0,    0,    0,    0,    0,    0,
+               0,    0,    0,    0,    0,    0,    0,    0,
+               0,    0,    0,    0,    0,    0,    0,    0,
+               0,    0,    0,    0,    0,    0,    0,    0,
+               0,    0,    0,    0,    0,    0,    0,    0,
+               0,    0,    0,    0,    0,    0,    0,    0,
+              10,   10,   10,   10,   10,   10,   10,   10,
+               0,    0,    0,    0,    0,    0,    0,    0,
+        ],
+        // Queen
+        [
+             -20,  -10,  -10,   -5,   -5,  -10,  -10,  -20,
+             -10,    0,    0,    0,    0,    0,    0,  -10,
+             -10,    0,   10,   10,   10,   10,    0,  -10,
+              -5,    0,   10,   15,   15,   10,    0,   -5,
+              -5,    0,   10,   15,   15,   10,    0,   -5,
+             -10,    0,   10,   10,   10,   10,    0,  -10,
+             -10,    0,    0,    0,    0,    0,    0,  -10,
+             -20,  -10,  -10,   -5,   -5,  -10,  -10,  -20,
+        ],
+        // King
+        [
+             -50,  -30,  -30,  -30,  -30,  -30,  -30,  -50,
+             -30,  -30,    0,    0,    0,    0,  -30,  -30,
+             -30,  -10,   20,   30,   30,   20,  -10,  -30,
+             -30,  -10,   30,   40,   40,   30,  -10,  -30,
+             -30,  -10,   30,   40,   40,   30,  -10,  -30,
+             -30,  -10,   20,   30,   30,   20,  -10,  -30,
+             -30,  -20,  -10,    0,    0,  -10,  -20,  -30,
+             -50,  -40,  -30,  -20,  -20,  -30,  -40,  -50,
+        ],
+    ],
+    mobility_weights: [
+        PhaseScore::new(0, 0),
+        PhaseScore::new(4, 4),
+        PhaseScore::new(5, 5),
+        PhaseScore::new(2, 4),
+        PhaseScore::new(1, 2),
+        PhaseScore::new(0, 0),
+    ],
+    doubled_pawn_penalty: 40,
+    isolated_pawn_penalty: 40,
+    passed_pawn_bonus: [
+        PhaseScore::new(0, 0),
+        PhaseScore::new(5, 10),
+        PhaseScore::new(10, 15),
+        PhaseScore::new(15, 30),
+        PhaseScore::new(30, 50),
+        PhaseScore::new(50, 80),
+        PhaseScore::new(70, 120),
+        PhaseScore::new(0, 0),
+    ],
+    connected_pawn_bonus: [
+        PhaseScore::new(0, 0),
+        PhaseScore::new(2, 2),
+        PhaseScore::new(4, 4),
+        PhaseScore::new(6, 8),
+        PhaseScore::new(10, 15),
+        PhaseScore::new(20, 30),
+        PhaseScore::new(40, 60),
+        PhaseScore::new(0, 0),
+    ],
+    protected_pawn_bonus: PhaseScore::new(5, 10),
+    backward_pawn_penalty: PhaseScore::new(10, 15),
+    pawn_shield_bonus: 10,
+    king_open_file_penalty: 20,
+    king_zone_attacker_penalty: 15,
+    rook_open_file_bonus: PhaseScore::new(25, 10),
+    rook_semi_open_file_bonus: PhaseScore::new(10, 5),
+    rook_on_seventh_bonus: PhaseScore::new(10, 20),
+    bishop_pair_bonus: PhaseScore::new(30, 50),
+    knight_outpost_bonus: PhaseScore::new(20, 10),
+};
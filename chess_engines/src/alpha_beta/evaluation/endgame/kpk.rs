@@ -0,0 +1,205 @@
+// King and pawn against king bitbase
+// Every position with white to move or black to move, the pawn on files a to d (the others are
+// mirrored) and ranks 2 to 7, is classified by retrograde analysis when first probed: positions
+// where the pawn promotes safely are wins and positions where black can take the pawn or is
+// stalemated are draws, then a position is a win for white when one white move reaches a win,
+// and for black to move when every black move does. What is never proven a win is a draw.
+//
+// The result is one bit per position, 24 kB.
+
+use std::sync::OnceLock;
+
+use chess_core::square::Square;
+
+/// Pawn squares times king squares times side to move
+const SIZE: usize = 24 * 64 * 64 * 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Result {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+fn index(white_to_move: bool, white_king: u8, black_king: u8, pawn: u8) -> usize {
+    let pawn_index = (pawn / 8 - 1) as usize * 4 + (pawn % 8) as usize;
+    ((pawn_index * 64 + white_king as usize) * 64 + black_king as usize) * 2
+        + white_to_move as usize
+}
+
+fn distance(a: u8, b: u8) -> u8 {
+    let (a, b) = (Square(a), Square(b));
+    a.rank().abs_diff(b.rank()).max(a.file().abs_diff(b.file()))
+}
+
+/// Squares next to `square`
+fn king_moves(square: u8) -> impl Iterator<Item = u8> {
+    let (rank, file) = ((square / 8) as i8, (square % 8) as i8);
+    (-1..=1)
+        .flat_map(move |dr| (-1..=1).map(move |df| (rank + dr, file + df)))
+        .filter(move |&(r, f)| (r, f) != (rank, file) && (0..8).contains(&r) && (0..8).contains(&f))
+        .map(|(r, f)| (r * 8 + f) as u8)
+}
+
+fn pawn_attacks(pawn: u8, square: u8) -> bool {
+    square / 8 == pawn / 8 + 1 && (square % 8).abs_diff(pawn % 8) == 1
+}
+
+fn initial_result(white_to_move: bool, white_king: u8, black_king: u8, pawn: u8) -> Result {
+    let push = pawn + 8;
+    if distance(white_king, black_king) <= 1
+        || white_king == pawn
+        || black_king == pawn
+        || (white_to_move && pawn_attacks(pawn, black_king))
+    {
+        Result::Invalid
+    } else if white_to_move
+        && pawn / 8 == 6
+        && white_king != push
+        && (distance(black_king, push) > 1 || distance(white_king, push) == 1)
+    {
+        // Promotes and the queen cannot be taken
+        Result::Win
+    } else if !white_to_move
+        && (king_moves(black_king)
+            .all(|to| distance(to, white_king) <= 1 || pawn_attacks(pawn, to))
+            || (distance(black_king, pawn) == 1 && distance(white_king, pawn) > 1))
+    {
+        // Stalemate or the pawn is taken
+        Result::Draw
+    } else {
+        Result::Unknown
+    }
+}
+
+/// Result of a position from the results of the positions after each move
+fn classify(
+    results: &[Result],
+    white_to_move: bool,
+    white_king: u8,
+    black_king: u8,
+    pawn: u8,
+) -> Result {
+    let mut reachable = [Result::Invalid; 10];
+    let mut count = 0;
+    let mut reach = |result| {
+        reachable[count] = result;
+        count += 1;
+    };
+    if white_to_move {
+        for to in king_moves(white_king) {
+            reach(results[index(false, to, black_king, pawn)]);
+        }
+        let push = pawn + 8;
+        if pawn / 8 < 6 && push != black_king && push != white_king {
+            reach(results[index(false, white_king, black_king, push)]);
+            if pawn / 8 == 1 && push + 8 != black_king && push + 8 != white_king {
+                reach(results[index(false, white_king, black_king, push + 8)]);
+            }
+        }
+    } else {
+        for to in king_moves(black_king) {
+            reach(results[index(true, white_king, to, pawn)]);
+        }
+    }
+    let reachable = &reachable[..count];
+    // Win for white when white can reach a win, or when black cannot avoid one
+    let (good, bad) = if white_to_move {
+        (Result::Win, Result::Draw)
+    } else {
+        (Result::Draw, Result::Win)
+    };
+    if reachable.contains(&good) {
+        good
+    } else if reachable.contains(&Result::Unknown) {
+        Result::Unknown
+    } else {
+        bad
+    }
+}
+
+fn generate() -> Vec<u64> {
+    let positions = || {
+        (0..24u8).flat_map(|pawn_index| {
+            let pawn = (pawn_index / 4 + 1) * 8 + pawn_index % 4;
+            (0..64u8).flat_map(move |white_king| {
+                (0..64u8).flat_map(move |black_king| {
+                    [false, true].map(|white_to_move| (white_to_move, white_king, black_king, pawn))
+                })
+            })
+        })
+    };
+    let mut results = vec![Result::Invalid; SIZE];
+    for (white_to_move, white_king, black_king, pawn) in positions() {
+        results[index(white_to_move, white_king, black_king, pawn)] =
+            initial_result(white_to_move, white_king, black_king, pawn);
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (white_to_move, white_king, black_king, pawn) in positions() {
+            let i = index(white_to_move, white_king, black_king, pawn);
+            if results[i] == Result::Unknown {
+                results[i] = classify(&results, white_to_move, white_king, black_king, pawn);
+                changed |= results[i] != Result::Unknown;
+            }
+        }
+    }
+
+    let mut bits = vec![0; SIZE / 64];
+    for (i, result) in results.into_iter().enumerate() {
+        if result == Result::Win {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+    bits
+}
+
+/// The side with the pawn wins, with the squares seen from that side: its pawn moves up
+pub fn probe(strong_king: Square, pawn: Square, weak_king: Square, strong_to_move: bool) -> bool {
+    static BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+    let bits = BITBASE.get_or_init(generate);
+    // Files e to h are mirrored
+    let flip = |square: Square| match pawn.file() {
+        0..=3 => square.0,
+        _ => square.0 ^ 7,
+    };
+    let i = index(
+        strong_to_move,
+        flip(strong_king),
+        flip(weak_king),
+        flip(pawn),
+    );
+    bits[i / 64] & (1 << (i % 64)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kpk() {
+        let square = |name: &str| Square::try_from(name).unwrap();
+        let probe = |king: &str, pawn: &str, enemy_king: &str, to_move: bool| {
+            probe(square(king), square(pawn), square(enemy_king), to_move)
+        };
+        // The king in front of its pawn with the opposition
+        assert!(probe("e6", "e5", "e8", true));
+        assert!(probe("e6", "e5", "e8", false));
+        // Without it
+        assert!(!probe("e4", "e3", "e6", true));
+        // Rook pawns are drawn when the other king reaches the corner
+        assert!(!probe("a6", "a5", "b8", true));
+        assert!(!probe("h6", "h5", "g8", true));
+        // The other king is outside the square of the pawn
+        assert!(probe("a1", "d5", "h5", true));
+        assert!(!probe("a1", "d5", "g5", false));
+        // The pawn is lost
+        assert!(!probe("a1", "d5", "e5", false));
+        // Key squares
+        assert!(probe("d6", "d4", "d8", true));
+        assert!(probe("d6", "d4", "d8", false));
+        assert!(probe("b6", "b5", "b8", false));
+    }
+}
@@ -0,0 +1,399 @@
+// Specialized endgames
+// The general evaluation does not know which material configurations are won or drawn: it
+// sees KBNvK as a small advantage with no plan to mate, and KBPvK with the wrong rook pawn as
+// a win. The material key of the position selects an evaluation function for known endings,
+// and otherwise a scale factor that reduces the general evaluation of drawish ones.
+//
+// Endgame functions score the position for the side with the advantage. Known wins get
+// KNOWN_WIN on top so that the search converts into them, and the rest of the score drives the
+// lone king to the edge, or to the right corner for KBNvK.
+
+mod kpk;
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use chess_core::{
+    color::Color,
+    square::Square,
+    state::{
+        bitboard::BitBoard,
+        chess_board::{ChessBoardSide, PieceType},
+        game_state::GameState,
+        material_key::MaterialKey,
+    },
+};
+
+use super::params::EvalParams;
+
+/// Above any evaluation of the material, below mate scores
+pub const KNOWN_WIN: i32 = 10_000;
+/// Scale factors are out of SCALE_NORMAL, 0 is a draw
+pub const SCALE_NORMAL: i32 = 64;
+
+/// Score of a position for the side with the advantage
+type EndgameFn = fn(&GameState, Color, &EvalParams) -> i32;
+
+#[derive(Clone, Copy)]
+pub struct Endgame {
+    /// Material signature, such as KBNvK
+    pub name: &'static str,
+    /// Side with the advantage
+    strong: Color,
+    function: EndgameFn,
+}
+
+impl Endgame {
+    /// Score for the side to move
+    pub fn evaluate(&self, state: &GameState, params: &EvalParams) -> i32 {
+        let score = (self.function)(state, self.strong, params);
+        if state.flags.active_color() == self.strong {
+            score
+        } else {
+            -score
+        }
+    }
+}
+
+fn registry() -> &'static HashMap<MaterialKey, Endgame> {
+    static REGISTRY: OnceLock<HashMap<MaterialKey, Endgame>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        let endgames: [(&str, EndgameFn); 8] = [
+            ("KvK", draw),
+            ("KNvK", draw),
+            ("KBvK", draw),
+            ("KNNvK", draw),
+            ("KPvK", kpk),
+            ("KRvK", kxk),
+            ("KQvK", kxk),
+            ("KBNvK", kbnk),
+        ];
+        let mut registry = HashMap::new();
+        for (name, function) in endgames {
+            let key = MaterialKey::from_signature(name).unwrap();
+            for (key, strong) in [(key, Color::White), (key.mirror(), Color::Black)] {
+                registry.insert(
+                    key,
+                    Endgame {
+                        name,
+                        strong,
+                        function,
+                    },
+                );
+            }
+        }
+        registry
+    })
+}
+
+/// Specialized evaluation of a material configuration, if there is one
+///
+/// Besides the registered signatures, a lone king against at least a rook worth of pieces is
+/// evaluated as KXvK.
+pub fn find(material_key: MaterialKey, params: &EvalParams) -> Option<Endgame> {
+    if let Some(endgame) = registry().get(&material_key) {
+        return Some(*endgame);
+    }
+    [Color::White, Color::Black]
+        .into_iter()
+        .find(|&strong| {
+            let lone_king = material_key.piece_count(!strong) == 0
+                && material_key.count(!strong, PieceType::Pawn) == 0;
+            lone_king
+                && piece_material(material_key, strong, params)
+                    >= params.piece_values[PieceType::Rook as usize]
+        })
+        .map(|strong| Endgame {
+            name: "KXvK",
+            strong,
+            function: kxk,
+        })
+}
+
+/// Reduction of the general evaluation, `strong` is the side it favors
+pub fn scale_factor(
+    state: &GameState,
+    material_key: MaterialKey,
+    strong: Color,
+    params: &EvalParams,
+) -> i32 {
+    let (own, enemy) = sides(state, strong);
+    let weak = !strong;
+    let count = |color, piece| material_key.count(color, piece);
+
+    // Rook pawns with at most a bishop that does not control the promotion square, the lone
+    // king holds the corner
+    let rook_pawns =
+        (own.pawn & !BitBoard::file(0)).is_empty() || (own.pawn & !BitBoard::file(7)).is_empty();
+    if !own.pawn.is_empty()
+        && rook_pawns
+        && material_key.piece_count(strong) == count(strong, PieceType::Bishop)
+        && count(strong, PieceType::Bishop) <= 1
+        && enemy.union() == enemy.king
+        && let (Some(pawn), Some(weak_king)) =
+            (own.pawn.get_first_square(), enemy.king.get_first_square())
+    {
+        let promotion = match strong {
+            Color::White => Square::new(7, pawn.file()),
+            Color::Black => Square::new(0, pawn.file()),
+        };
+        let bishop_controls = own
+            .bishop
+            .get_first_square()
+            .is_some_and(|bishop| is_light(bishop) == is_light(promotion));
+        if distance(weak_king, promotion) <= 1 && !bishop_controls {
+            return 0;
+        }
+    }
+
+    // Without pawns, a minor piece ahead is not enough
+    let advantage =
+        piece_material(material_key, strong, params) - piece_material(material_key, weak, params);
+    if own.pawn.is_empty() && advantage <= params.piece_values[PieceType::Bishop as usize] {
+        let mating = piece_material(material_key, strong, params)
+            >= params.piece_values[PieceType::Rook as usize];
+        return if mating { 16 } else { 0 };
+    }
+
+    // Opposite colored bishops
+    if count(strong, PieceType::Bishop) == 1
+        && count(weak, PieceType::Bishop) == 1
+        && let (Some(bishop), Some(enemy_bishop)) = (
+            own.bishop.get_first_square(),
+            enemy.bishop.get_first_square(),
+        )
+        && is_light(bishop) != is_light(enemy_bishop)
+    {
+        let only_bishops =
+            material_key.piece_count(strong) == 1 && material_key.piece_count(weak) == 1;
+        return if only_bishops { 24 } else { 48 };
+    }
+
+    SCALE_NORMAL
+}
+
+fn sides(state: &GameState, strong: Color) -> (&ChessBoardSide, &ChessBoardSide) {
+    match strong {
+        Color::White => (&state.boards.white, &state.boards.black),
+        Color::Black => (&state.boards.black, &state.boards.white),
+    }
+}
+
+/// Value of the pieces of `color` other than pawns
+fn piece_material(material_key: MaterialKey, color: Color, params: &EvalParams) -> i32 {
+    [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ]
+    .into_iter()
+    .map(|piece| params.piece_values[piece as usize] * material_key.count(color, piece) as i32)
+    .sum()
+}
+
+fn is_light(square: Square) -> bool {
+    BitBoard::LIGHT_SQUARES.get(square)
+}
+
+fn distance(a: Square, b: Square) -> i32 {
+    a.rank().abs_diff(b.rank()).max(a.file().abs_diff(b.file())) as i32
+}
+
+/// From 0 in the center to 120 in the corners
+fn push_to_edge(square: Square) -> i32 {
+    let edge_distance = |x: u8| 3 - x.min(7 - x) as i32;
+    20 * (edge_distance(square.rank()) + edge_distance(square.file()))
+}
+
+/// From 20 when the kings are in opposite corners to 120 when they are in opposition
+fn push_close(a: Square, b: Square) -> i32 {
+    20 * (8 - distance(a, b))
+}
+
+fn kings(state: &GameState, strong: Color) -> (Square, Square) {
+    let (own, enemy) = sides(state, strong);
+    (
+        own.king.get_first_square().unwrap(),
+        enemy.king.get_first_square().unwrap(),
+    )
+}
+
+fn draw(_state: &GameState, _strong: Color, _params: &EvalParams) -> i32 {
+    0
+}
+
+/// Lone king against enough material to mate: the lone king is driven to the edge
+fn kxk(state: &GameState, strong: Color, params: &EvalParams) -> i32 {
+    let (own, _) = sides(state, strong);
+    let (strong_king, weak_king) = kings(state, strong);
+    let material: i32 = own
+        .as_array()
+        .into_iter()
+        .map(|(board, piece)| params.piece_values[piece as usize] * board.count_ones() as i32)
+        .sum();
+    let mating = !own.queen.is_empty()
+        || !own.rook.is_empty()
+        || (!own.bishop.is_empty() && !own.knight.is_empty())
+        || (!(own.bishop & BitBoard::LIGHT_SQUARES).is_empty()
+            && !(own.bishop & !BitBoard::LIGHT_SQUARES).is_empty());
+    let score = material + push_to_edge(weak_king) + push_close(strong_king, weak_king);
+    if mating { KNOWN_WIN + score } else { score }
+}
+
+/// Mate can only be forced in a corner of the color of the bishop
+fn kbnk(state: &GameState, strong: Color, params: &EvalParams) -> i32 {
+    let (own, _) = sides(state, strong);
+    let (strong_king, weak_king) = kings(state, strong);
+    let bishop = own.bishop.get_first_square().unwrap();
+    // Mirrored so that the corners to reach are a1 and h8, the dark corners
+    let king = if is_light(bishop) {
+        Square::new(weak_king.rank(), 7 - weak_king.file())
+    } else {
+        weak_king
+    };
+    // 7 in a1 and h8, 0 on the long diagonal between the wrong corners
+    let corner = (7 - king.rank() as i32 - king.file() as i32).abs();
+    KNOWN_WIN
+        + params.piece_values[PieceType::Bishop as usize]
+        + params.piece_values[PieceType::Knight as usize]
+        + 40 * corner
+        + push_close(strong_king, weak_king)
+}
+
+/// Exact with the bitbase, the pawn is worth more as it advances
+fn kpk(state: &GameState, strong: Color, params: &EvalParams) -> i32 {
+    let (own, _) = sides(state, strong);
+    let (strong_king, weak_king) = kings(state, strong);
+    // Seen from the strong side
+    let orient = |square: Square| match strong {
+        Color::White => square,
+        Color::Black => square.mirror(),
+    };
+    let pawn = orient(own.pawn.get_first_square().unwrap());
+    let strong_to_move = state.flags.active_color() == strong;
+    if kpk::probe(orient(strong_king), pawn, orient(weak_king), strong_to_move) {
+        KNOWN_WIN + params.piece_values[PieceType::Pawn as usize] + 10 * pawn.rank() as i32
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::default_params::DEFAULT_PARAMS;
+    use super::*;
+
+    fn evaluate(fen: &str) -> Option<(&'static str, i32)> {
+        let state = &GameState::from_fen(fen.to_string());
+        let endgame = find(MaterialKey::from_state(state), &DEFAULT_PARAMS)?;
+        Some((endgame.name, endgame.evaluate(state, &DEFAULT_PARAMS)))
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(
+            evaluate("8/8/8/3k4/8/8/8/3NK3 w - - 0 1"),
+            Some(("KNvK", 0))
+        );
+        assert_eq!(
+            evaluate("8/8/8/3k4/8/8/8/2NNK3 b - - 0 1"),
+            Some(("KNNvK", 0))
+        );
+        assert_eq!(
+            evaluate("8/8/3b4/3k4/8/8/8/4K3 w - - 0 1"),
+            Some(("KBvK", 0))
+        );
+        // The side with the advantage is not the side to move
+        let (name, score) = evaluate("8/8/3q4/3k4/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(name, "KQvK");
+        assert!(score < -KNOWN_WIN);
+        let (name, score) = evaluate("8/8/8/3k4/8/8/8/2RRK3 b - - 0 1").unwrap();
+        assert_eq!(name, "KXvK");
+        assert!(score < -KNOWN_WIN);
+        // Not enough to mate
+        let (name, score) = evaluate("8/8/8/3k4/8/8/8/1NNNK3 w - - 0 1").unwrap();
+        assert_eq!(name, "KXvK");
+        assert!(score > 0 && score < KNOWN_WIN);
+        // Pawns on both sides
+        assert!(evaluate("8/8/3p4/3k4/8/8/8/2RRK3 w - - 0 1").is_none());
+    }
+
+    #[test]
+    fn test_kxk() {
+        // The lone king is better in the center, and the other king should come closer
+        let center = evaluate("8/8/8/3k4/8/8/8/R3K3 w - - 0 1").unwrap().1;
+        let edge = evaluate("k7/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap().1;
+        let close = evaluate("3k4/8/3K4/8/8/8/8/R7 w - - 0 1").unwrap().1;
+        assert!(KNOWN_WIN < center && center < edge && edge < close);
+    }
+
+    #[test]
+    fn test_kbnk() {
+        // Dark squared bishop, mate in a1 or h8
+        let right_corner = evaluate("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1").unwrap().1;
+        let wrong_corner = evaluate("k7/8/2K5/8/8/8/8/2B1N3 w - - 0 1").unwrap().1;
+        let center = evaluate("8/8/8/4k3/8/2K5/8/2B1N3 w - - 0 1").unwrap().1;
+        assert!(KNOWN_WIN < wrong_corner && wrong_corner < center && center < right_corner);
+        // Light squared bishop
+        let right_corner = evaluate("k7/8/2K5/8/8/8/8/3BN3 w - - 0 1").unwrap().1;
+        assert!(right_corner > center);
+    }
+
+    #[test]
+    fn test_kpk() {
+        let (name, score) = evaluate("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(name, "KPvK");
+        assert!(score < -KNOWN_WIN);
+        assert_eq!(
+            evaluate("8/8/8/8/4k3/8/4P3/4K3 w - - 0 1"),
+            Some(("KPvK", 0))
+        );
+        // Black pawn
+        assert!(evaluate("8/8/8/8/3p4/3k4/8/3K4 b - - 0 1").unwrap().1 > KNOWN_WIN);
+        assert_eq!(evaluate("1k6/8/8/8/8/p7/8/K7 b - - 0 1"), Some(("KPvK", 0)));
+    }
+
+    #[test]
+    fn test_scale_factor() {
+        let scale = |fen: &str, strong: Color| {
+            let state = &GameState::from_fen(fen.to_string());
+            scale_factor(
+                state,
+                MaterialKey::from_state(state),
+                strong,
+                &DEFAULT_PARAMS,
+            )
+        };
+        // Wrong rook pawn, or the right one
+        assert_eq!(scale("k7/8/8/8/P7/8/P7/2B1K3 w - - 0 1", Color::White), 0);
+        assert_eq!(
+            scale("k7/8/8/8/P7/8/P7/3BK3 w - - 0 1", Color::White),
+            SCALE_NORMAL
+        );
+        assert_eq!(
+            scale("8/8/8/5k2/P7/8/8/3BK3 w - - 0 1", Color::White),
+            SCALE_NORMAL
+        );
+        assert_eq!(scale("k7/8/8/8/7p/8/8/7K b - - 0 1", Color::Black), 0);
+        // A minor piece ahead without pawns
+        assert_eq!(
+            scale("8/8/8/3k4/8/8/1P6/1B1K4 w - - 0 1", Color::White),
+            SCALE_NORMAL
+        );
+        assert_eq!(scale("8/8/8/3k4/3p4/8/8/1B1K4 w - - 0 1", Color::White), 0);
+        assert_eq!(scale("8/8/8/3k4/3b4/8/8/1R1K4 w - - 0 1", Color::White), 16);
+        // Opposite colored bishops
+        assert_eq!(
+            scale("8/4k1b1/8/8/4P3/2PB4/8/4K3 w - - 0 1", Color::White),
+            24
+        );
+        assert_eq!(
+            scale("8/4k1b1/8/r7/4P3/2PB4/R7/4K3 w - - 0 1", Color::White),
+            48
+        );
+        assert_eq!(
+            scale("8/4kb2/8/8/4P3/2PB4/8/4K3 w - - 0 1", Color::White),
+            SCALE_NORMAL
+        );
+    }
+}
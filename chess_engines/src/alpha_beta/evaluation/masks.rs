@@ -0,0 +1,97 @@
+// Bitboard masks used by the evaluation terms
+// Pawn terms only look at pawn bitboards: a pawn is passed when the mask of the squares in
+// front of it on its file and the adjacent files holds no enemy pawn, and so on.
+
+use chess_core::{color::Color, square::Square, state::bitboard::BitBoard};
+
+/// Files next to `file`, not including it
+pub(super) fn adjacent_files(file: u8) -> BitBoard {
+    let mut files = BitBoard::EMPTY;
+    if file > 0 {
+        files |= BitBoard::file(file - 1);
+    }
+    if file < 7 {
+        files |= BitBoard::file(file + 1);
+    }
+    files
+}
+
+/// Ranks strictly in front of `rank` from the point of view of `color`
+pub(super) fn forward_ranks(color: Color, rank: u8) -> BitBoard {
+    let mut ranks = BitBoard::EMPTY;
+    for r in 0..8 {
+        let in_front = match color {
+            Color::White => r > rank,
+            Color::Black => r < rank,
+        };
+        if in_front {
+            ranks |= BitBoard::rank(r);
+        }
+    }
+    ranks
+}
+
+/// Rank of `square` counted from the first rank of `color`
+pub(super) fn relative_rank(color: Color, square: Square) -> u8 {
+    match color {
+        Color::White => square.rank(),
+        Color::Black => 7 - square.rank(),
+    }
+}
+
+/// Squares in front of a pawn on `square` where an enemy pawn can stop it
+pub(super) fn passed_pawn_mask(color: Color, square: Square) -> BitBoard {
+    (BitBoard::file(square.file()) | adjacent_files(square.file()))
+        & forward_ranks(color, square.rank())
+}
+
+/// Squares in front of a pawn on `square` from which an enemy pawn can attack it
+pub(super) fn attack_span(color: Color, square: Square) -> BitBoard {
+    adjacent_files(square.file()) & forward_ranks(color, square.rank())
+}
+
+/// Squares attacked by the `pawns` of `color`
+pub(super) fn pawn_attacks(color: Color, pawns: BitBoard) -> BitBoard {
+    let (not_a_file, not_h_file) = (!BitBoard::file(0), !BitBoard::file(7));
+    match color {
+        Color::White => ((pawns & not_a_file) << 7) | ((pawns & not_h_file) << 9),
+        Color::Black => ((pawns & not_h_file) >> 7) | ((pawns & not_a_file) >> 9),
+    }
+}
+
+/// Square in front of `square` for `color`
+pub(super) fn forward_square(color: Color, square: Square) -> Option<Square> {
+    match (color, square.rank()) {
+        (Color::White, 7) | (Color::Black, 0) => None,
+        (Color::White, rank) => Some(Square::new(rank + 1, square.file())),
+        (Color::Black, rank) => Some(Square::new(rank - 1, square.file())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks() {
+        let e4 = Square::new(3, 4);
+        assert_eq!(passed_pawn_mask(Color::White, e4).count_ones(), 12);
+        assert_eq!(passed_pawn_mask(Color::Black, e4).count_ones(), 9);
+        assert_eq!(attack_span(Color::White, e4).count_ones(), 8);
+        assert_eq!(
+            passed_pawn_mask(Color::Black, Square::new(6, 0)).count_ones(),
+            12
+        );
+        assert_eq!(relative_rank(Color::Black, e4), 4);
+        assert_eq!(forward_square(Color::Black, e4), Some(Square::new(2, 4)));
+
+        // The a-file and h-file pawns do not wrap around the board
+        let pawns = (BitBoard::file(0) | BitBoard::file(7)) & BitBoard::rank(1);
+        let mut expected = BitBoard::from(Square::new(2, 1));
+        expected.set(Square::new(2, 6));
+        assert_eq!(pawn_attacks(Color::White, pawns), expected);
+        let mut expected = BitBoard::from(Square::new(0, 1));
+        expected.set(Square::new(0, 6));
+        assert_eq!(pawn_attacks(Color::Black, pawns), expected);
+    }
+}
@@ -1 +1,110 @@
-pub mod simple_eval;
\ No newline at end of file
+use chess_core::{
+    r#move::Move,
+    state::{game_state::GameState, material_key::MaterialKey},
+};
+
+mod default_params;
+pub mod endgame;
+mod masks;
+pub mod nnue;
+pub mod params;
+pub mod pawn_hash;
+pub mod phase_score;
+pub mod simple_eval;
+pub mod trace;
+
+pub use nnue::NnueEvaluator;
+pub use params::EvalParams;
+pub use simple_eval::SimpleEvaluator;
+pub use trace::EvalTrace;
+
+/// Static evaluation used by the search
+///
+/// Evaluators that keep state between positions (accumulators, caches) can follow the search
+/// with the hooks, which do nothing by default. Null moves do not call any hook. Each search
+/// thread uses its own clone of the evaluator.
+pub trait Evaluator: Clone + Send {
+    /// Score of the position for the side to move, in centipawns
+    ///
+    /// Checkmates and draws are scored by the search
+    fn evaluate(&mut self, state: &GameState) -> i32;
+
+    /// Same as `evaluate`, with the keys of the position kept by the search for evaluators
+    /// that cache pawn structures or look up material configurations
+    fn evaluate_with_keys(
+        &mut self,
+        state: &GameState,
+        _pawn_hash: u64,
+        _material_key: MaterialKey,
+    ) -> i32 {
+        self.evaluate(state)
+    }
+
+    /// Called at the start of a search with the root position, which may have changed in any
+    /// way since the last search
+    fn reset(&mut self, _state: &GameState) {}
+
+    /// Called with the state before `m` is made
+    fn make_move(&mut self, _state: &GameState, _m: Move) {}
+
+    /// Called with the state after `m` is unmade
+    fn unmake_move(&mut self, _state: &GameState, _m: Move) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use chess_core::{r#move::MoveCode, square::Square};
+
+    use super::super::search::tests::TestPosition;
+    use super::*;
+
+    /// Material only, and counts the moves made and not unmade
+    #[derive(Clone, Default)]
+    struct TestEvaluator {
+        resets: usize,
+        moves_made: i32,
+    }
+
+    impl Evaluator for TestEvaluator {
+        fn evaluate(&mut self, state: &GameState) -> i32 {
+            let (active, passive) = state.split_boards();
+            let material = |side: &chess_core::state::chess_board::ChessBoardSide| {
+                100 * side.pawn.count_ones() as i32
+                    + 300 * (side.knight.count_ones() + side.bishop.count_ones()) as i32
+                    + 500 * side.rook.count_ones() as i32
+                    + 900 * side.queen.count_ones() as i32
+            };
+            material(active) - material(passive)
+        }
+
+        fn reset(&mut self, _state: &GameState) {
+            self.resets += 1;
+        }
+
+        fn make_move(&mut self, _state: &GameState, _m: Move) {
+            self.moves_made += 1;
+        }
+
+        fn unmake_move(&mut self, _state: &GameState, _m: Move) {
+            self.moves_made -= 1;
+        }
+    }
+
+    #[test]
+    fn test_custom_evaluator() {
+        // White can take the queen with the bishop
+        let position = &mut TestPosition::new(
+            "rnb1kbnr/pppp1ppp/8/4p1q1/4P3/3P4/PPP2PPP/RNBQKBNR w KQkq - 0 1",
+        );
+        let mut search_ctx = position.search_ctx_with(TestEvaluator::default());
+        let (score, pv) = search_ctx.iterative_deepen_to_depth(3);
+
+        assert!(score > 500, "{}", score);
+        assert_eq!(
+            pv.first(),
+            Some(&Move::new(Square(2), Square(38), MoveCode::Capture))
+        );
+        assert_eq!(search_ctx.evaluator.resets, 1);
+        assert_eq!(search_ctx.evaluator.moves_made, 0);
+    }
+}
@@ -0,0 +1,361 @@
+// NNUE evaluation
+// HalfKP features: each perspective sees the board with its own pieces as white (the board is
+// flipped for black), and every (own king square, piece, square) triple of a piece other than
+// a king is an input. The feature transformer sums the weights of the active inputs into one
+// accumulator per perspective, and a single output layer reads the clipped accumulators of
+// the side to move and of the other side.
+//
+// A move only changes the features of a few pieces, so the accumulators are updated from the
+// previous ones when a move is made, except for the perspective of a king that moved, whose
+// features all change. Every position from the root of the search keeps its accumulators,
+// unmaking a move drops the last ones.
+
+mod network;
+mod simd;
+
+use std::{path::Path, sync::Arc};
+
+use chess_core::{
+    color::Color,
+    r#move::{Move, MoveCode},
+    square::Square,
+    state::{chess_board::PieceType, game_state::GameState},
+};
+
+pub use network::{FEATURES, Network, QA, QB, SCALE};
+
+use super::Evaluator;
+
+/// Keeps the scores of the network away from mate scores
+const MAX_SCORE: i32 = 30_000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Piece {
+    color: Color,
+    piece: PieceType,
+    square: Square,
+}
+
+/// Pieces removed and added by a move, including kings
+#[derive(Default)]
+struct Changes {
+    removed: [Option<Piece>; 2],
+    added: [Option<Piece>; 2],
+}
+
+impl Changes {
+    fn new(state: &GameState, m: Move) -> Self {
+        let color = state.flags.active_color();
+        let (active, passive) = state.split_boards();
+        let (from, to, code) = (m.from(), m.to(), m.code());
+        let moved = active.piece_at(from).expect("No piece on the from square");
+        let piece = |color, piece, square| {
+            Some(Piece {
+                color,
+                piece,
+                square,
+            })
+        };
+
+        let mut changes = Changes {
+            removed: [piece(color, moved, from), None],
+            added: [piece(color, code.promotion().unwrap_or(moved), to), None],
+        };
+        if code == MoveCode::EnPassant {
+            let square = Square::new(from.rank(), to.file());
+            changes.removed[1] = piece(!color, PieceType::Pawn, square);
+        } else if code.is_capture() {
+            let captured = passive
+                .piece_at(to)
+                .expect("No piece on the captured square");
+            changes.removed[1] = piece(!color, captured, to);
+        } else if code.is_castle() {
+            let (rook_from, rook_to) = match code {
+                MoveCode::KingCastle => (7, 5),
+                _ => (0, 3),
+            };
+            let rank = from.rank();
+            changes.removed[1] = piece(color, PieceType::Rook, Square::new(rank, rook_from));
+            changes.added[1] = piece(color, PieceType::Rook, Square::new(rank, rook_to));
+        }
+        changes
+    }
+}
+
+/// Input of the network for `piece` seen by `perspective`, whose king is on `king`
+fn feature(perspective: Color, king: Square, piece: Piece) -> Option<usize> {
+    if piece.piece == PieceType::King {
+        return None;
+    }
+    let orient = |square: Square| match perspective {
+        Color::White => square.0 as usize,
+        Color::Black => square.mirror().0 as usize,
+    };
+    let relative_color = if piece.color == perspective { 0 } else { 1 };
+    Some(
+        orient(king) * 640
+            + (relative_color * 5 + piece.piece as usize) * 64
+            + orient(piece.square),
+    )
+}
+
+fn pieces(state: &GameState) -> impl Iterator<Item = Piece> + '_ {
+    [
+        (Color::White, &state.boards.white),
+        (Color::Black, &state.boards.black),
+    ]
+    .into_iter()
+    .flat_map(|(color, side)| {
+        side.as_array().into_iter().flat_map(move |(board, piece)| {
+            let mut board = *board;
+            std::iter::from_fn(move || board.pop_first_square()).map(move |square| Piece {
+                color,
+                piece,
+                square,
+            })
+        })
+    })
+}
+
+/// Evaluation by a neural network, the network is shared by the clones of the evaluator
+#[derive(Clone)]
+pub struct NnueEvaluator {
+    network: Arc<Network>,
+    /// Accumulators of the positions from the root to the current one, white then black
+    accumulators: Vec<i16>,
+}
+
+impl NnueEvaluator {
+    pub fn new(network: Network) -> Self {
+        NnueEvaluator {
+            network: Arc::new(network),
+            accumulators: Vec::new(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        Network::load(path).map(Self::new)
+    }
+
+    /// Accumulator of `perspective` computed from scratch, for `state` after `changes`
+    fn refresh(
+        &self,
+        state: &GameState,
+        changes: &Changes,
+        perspective: Color,
+        accumulator: &mut [i16],
+    ) {
+        let is_king = |piece: &Piece| piece.color == perspective && piece.piece == PieceType::King;
+        let king = changes.added.iter().flatten().copied().find(is_king);
+        let king = king
+            .or_else(|| pieces(state).find(is_king))
+            .expect("No king on the board")
+            .square;
+        accumulator.copy_from_slice(&self.network.feature_biases);
+        let pieces = pieces(state)
+            .filter(|piece| !changes.removed.contains(&Some(*piece)))
+            .chain(changes.added.iter().flatten().copied());
+        for piece in pieces {
+            if let Some(feature) = feature(perspective, king, piece) {
+                simd::add(accumulator, self.network.feature_weights(feature));
+            }
+        }
+    }
+
+    fn current(&self) -> &[i16] {
+        let size = 2 * self.network.hidden_size();
+        &self.accumulators[self.accumulators.len() - size..]
+    }
+}
+
+impl Evaluator for NnueEvaluator {
+    fn evaluate(&mut self, state: &GameState) -> i32 {
+        if self.accumulators.is_empty() {
+            self.reset(state);
+        }
+        let (white, black) = self.current().split_at(self.network.hidden_size());
+        let (us, them) = match state.flags.active_color() {
+            Color::White => (white, black),
+            Color::Black => (black, white),
+        };
+        let output = simd::output(us, them, &self.network.output_weights)
+            .wrapping_add(self.network.output_bias);
+        let score = output as i64 * SCALE as i64 / (QA * QB) as i64;
+        score.clamp(-MAX_SCORE as i64, MAX_SCORE as i64) as i32
+    }
+
+    fn reset(&mut self, state: &GameState) {
+        let size = self.network.hidden_size();
+        let mut accumulators = std::mem::take(&mut self.accumulators);
+        accumulators.clear();
+        accumulators.resize(2 * size, 0);
+        let (white, black) = accumulators.split_at_mut(size);
+        self.refresh(state, &Changes::default(), Color::White, white);
+        self.refresh(state, &Changes::default(), Color::Black, black);
+        self.accumulators = accumulators;
+    }
+
+    fn make_move(&mut self, state: &GameState, m: Move) {
+        if self.accumulators.is_empty() {
+            self.reset(state);
+        }
+        let size = self.network.hidden_size();
+        let start = self.accumulators.len();
+        self.accumulators.extend_from_within(start - 2 * size..);
+
+        let changes = Changes::new(state, m);
+        let mut accumulators = std::mem::take(&mut self.accumulators);
+        let (white, black) = accumulators[start..].split_at_mut(size);
+        for (perspective, accumulator) in [(Color::White, white), (Color::Black, black)] {
+            let king_moved = changes.removed[0]
+                .is_some_and(|piece| piece.color == perspective && piece.piece == PieceType::King);
+            if king_moved {
+                self.refresh(state, &changes, perspective, accumulator);
+                continue;
+            }
+            let king = match perspective {
+                Color::White => state.boards.white.king,
+                Color::Black => state.boards.black.king,
+            }
+            .get_first_square()
+            .expect("No king on the board");
+            for piece in changes.removed.iter().flatten() {
+                if let Some(feature) = feature(perspective, king, *piece) {
+                    simd::sub(accumulator, self.network.feature_weights(feature));
+                }
+            }
+            for piece in changes.added.iter().flatten() {
+                if let Some(feature) = feature(perspective, king, *piece) {
+                    simd::add(accumulator, self.network.feature_weights(feature));
+                }
+            }
+        }
+        self.accumulators = accumulators;
+    }
+
+    fn unmake_move(&mut self, _state: &GameState, _m: Move) {
+        let size = 2 * self.network.hidden_size();
+        self.accumulators.truncate(self.accumulators.len() - size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chess_core::{r#move::MoveGenerator, state::make_unmake::MakeUnmaker};
+
+    use super::super::super::search::tests::TestPosition;
+    use super::*;
+
+    /// Network with pseudo-random weights and a material bias on the pawn features
+    fn test_network() -> Network {
+        let hidden_size = 16;
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |range: i16| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % (2 * range as u64 + 1)) as i16 - range
+        };
+        let feature_biases = (0..hidden_size).map(|_| random(50)).collect();
+        let feature_weights = (0..FEATURES * hidden_size).map(|_| random(30)).collect();
+        let output_weights = (0..2 * hidden_size).map(|_| random(60)).collect();
+        Network::new(
+            hidden_size,
+            feature_biases,
+            feature_weights,
+            output_weights,
+            1000,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_network_file() {
+        let network = test_network();
+        let bytes = network.to_bytes();
+        assert_eq!(bytes.len(), 12 + 2 * (16 + FEATURES * 16 + 32) + 4);
+        assert_eq!(Network::from_bytes(&bytes), Ok(network));
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Network::from_bytes(b"NNUE\x02\0\0\0").is_err());
+        assert!(Network::new(8, vec![0; 8], vec![0; FEATURES * 8], vec![0; 16], 0).is_err());
+    }
+
+    #[test]
+    fn test_incremental_updates() {
+        // Castling, en passant, promotions with and without capture
+        let games = [
+            (
+                "r3k2r/pPppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "e1g1 e8g8 a2a4 b4a3 b7a8q h3g2 d5d6 g2f1n g1f1",
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "e2e4 d7d5 e4e5 f7f5 e5f6 e8f7 e1e2 g8h6 f6g7 f7e6 g7h8r",
+            ),
+        ];
+        let move_generator = MoveGenerator::new();
+        for (fen, moves) in games {
+            let mut evaluator = NnueEvaluator::new(test_network());
+            let state = &mut GameState::from_fen(fen.to_string());
+            evaluator.reset(state);
+            let make_unmaker = &mut MakeUnmaker::new(state);
+            let mut made = Vec::new();
+            for uci in moves.split_whitespace() {
+                let mut legal = Vec::new();
+                move_generator.get_pseudo_legal_moves(make_unmaker.state, &mut legal);
+                let m = *legal
+                    .iter()
+                    .find(|m| m.to_string().to_lowercase() == uci)
+                    .unwrap_or_else(|| panic!("{} not found", uci));
+                evaluator.make_move(make_unmaker.state, m);
+                make_unmaker.make_move(m);
+                made.push(m);
+
+                let mut fresh = NnueEvaluator::new(test_network());
+                fresh.reset(make_unmaker.state);
+                assert_eq!(evaluator.current(), fresh.current(), "after {}", uci);
+                assert_eq!(
+                    evaluator.evaluate(make_unmaker.state),
+                    fresh.evaluate(make_unmaker.state)
+                );
+            }
+            for m in made.into_iter().rev() {
+                make_unmaker.unmake_move(m);
+                evaluator.unmake_move(make_unmaker.state, m);
+            }
+            let mut fresh = NnueEvaluator::new(test_network());
+            fresh.reset(make_unmaker.state);
+            assert_eq!(evaluator.accumulators, fresh.accumulators);
+        }
+    }
+
+    #[test]
+    fn test_symmetry() {
+        // The same position with the colors swapped has the same score for the side to move
+        let evaluator = &mut NnueEvaluator::new(test_network());
+        let white = GameState::from_fen(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3".to_string(),
+        );
+        let black = GameState::from_fen(
+            "rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3".to_string(),
+        );
+        evaluator.reset(&white);
+        let score = evaluator.evaluate(&white);
+        evaluator.reset(&black);
+        assert_eq!(evaluator.evaluate(&black), score);
+    }
+
+    #[test]
+    fn test_search() {
+        let position =
+            &mut TestPosition::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mut search_ctx = position.search_ctx_with(NnueEvaluator::new(test_network()));
+        let (_, pv) = search_ctx.iterative_deepen_to_depth(4);
+        assert!(!pv.is_empty());
+        // Every move made by the search was unmade
+        assert_eq!(
+            search_ctx.evaluator.accumulators.len(),
+            2 * search_ctx.evaluator.network.hidden_size()
+        );
+    }
+}
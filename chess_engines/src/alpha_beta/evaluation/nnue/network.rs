@@ -0,0 +1,164 @@
+// Network file format
+// All integers are little-endian, weights are quantized to i16.
+//
+//   magic            4 bytes, "NNUE"
+//   version          u32, 1
+//   hidden size      u32, size H of the accumulator of one perspective, a multiple of 16
+//   feature biases   H x i16
+//   feature weights  FEATURES x H x i16, the H weights of feature 0 first
+//   output weights   2H x i16, side to move first
+//   output bias      i32
+//
+// The feature transformer is quantized by QA and the output weights by QB: the output of the
+// network is (bias + sum of clamp(accumulator, 0, QA) * weight) * SCALE / (QA * QB).
+
+use std::path::Path;
+
+/// Number of inputs of the feature transformer, see `feature` for the indexing
+pub const FEATURES: usize = 64 * 640;
+/// Quantization of the feature transformer, also the upper bound of the clipped ReLU
+pub const QA: i32 = 255;
+/// Quantization of the output weights
+pub const QB: i32 = 64;
+/// Centipawns per unit of output of the network
+pub const SCALE: i32 = 400;
+
+const MAGIC: &[u8; 4] = b"NNUE";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Network {
+    hidden_size: usize,
+    pub(super) feature_biases: Vec<i16>,
+    pub(super) feature_weights: Vec<i16>,
+    pub(super) output_weights: Vec<i16>,
+    pub(super) output_bias: i32,
+}
+
+impl Network {
+    /// Checks that the weights have the sizes given by `hidden_size`
+    pub fn new(
+        hidden_size: usize,
+        feature_biases: Vec<i16>,
+        feature_weights: Vec<i16>,
+        output_weights: Vec<i16>,
+        output_bias: i32,
+    ) -> Result<Self, String> {
+        if hidden_size == 0 || !hidden_size.is_multiple_of(16) {
+            return Err(format!(
+                "Hidden size must be a positive multiple of 16, found {}",
+                hidden_size
+            ));
+        }
+        let sizes = [
+            ("feature biases", feature_biases.len(), hidden_size),
+            (
+                "feature weights",
+                feature_weights.len(),
+                FEATURES * hidden_size,
+            ),
+            ("output weights", output_weights.len(), 2 * hidden_size),
+        ];
+        for (name, size, expected) in sizes {
+            if size != expected {
+                return Err(format!("Expected {} {}, found {}", expected, name, size));
+            }
+        }
+        Ok(Network {
+            hidden_size,
+            feature_biases,
+            feature_weights,
+            output_weights,
+            output_bias,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader(bytes);
+        if reader.take(4)? != MAGIC {
+            return Err("Not a network file".to_string());
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("Unsupported network version {}", version));
+        }
+        let hidden_size = reader.u32()? as usize;
+        if hidden_size == 0 || hidden_size > 1 << 16 {
+            return Err(format!("Invalid hidden size {}", hidden_size));
+        }
+        let feature_biases = reader.i16s(hidden_size)?;
+        let feature_weights = reader.i16s(FEATURES * hidden_size)?;
+        let output_weights = reader.i16s(2 * hidden_size)?;
+        let output_bias = reader.i32()?;
+        if !reader.0.is_empty() {
+            return Err(format!("{} unexpected bytes at the end", reader.0.len()));
+        }
+        Self::new(
+            hidden_size,
+            feature_biases,
+            feature_weights,
+            output_weights,
+            output_bias,
+        )
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend((self.hidden_size as u32).to_le_bytes());
+        for weights in [
+            &self.feature_biases,
+            &self.feature_weights,
+            &self.output_weights,
+        ] {
+            bytes.extend(weights.iter().flat_map(|weight| weight.to_le_bytes()));
+        }
+        bytes.extend(self.output_bias.to_le_bytes());
+        bytes
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    pub(super) fn feature_weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden_size..(feature + 1) * self.hidden_size]
+    }
+}
+
+/// Remaining bytes of a network file
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < size {
+            return Err("Network file is truncated".to_string());
+        }
+        let (bytes, rest) = self.0.split_at(size);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i16s(&mut self, count: usize) -> Result<Vec<i16>, String> {
+        Ok(self
+            .take(2 * count)?
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect())
+    }
+}
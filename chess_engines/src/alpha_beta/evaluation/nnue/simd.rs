@@ -0,0 +1,142 @@
+// Vector operations of the network
+// The scalar versions are always available. With the simd feature on x86_64, AVX2 versions are
+// used when the processor supports them. Both wrap on overflow so that they give the same
+// results.
+
+use super::network::QA;
+
+/// `accumulator += weights`
+pub(super) fn add(accumulator: &mut [i16], weights: &[i16]) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is supported
+        return unsafe { avx2::add(accumulator, weights) };
+    }
+    scalar::add(accumulator, weights)
+}
+
+/// `accumulator -= weights`
+pub(super) fn sub(accumulator: &mut [i16], weights: &[i16]) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is supported
+        return unsafe { avx2::sub(accumulator, weights) };
+    }
+    scalar::sub(accumulator, weights)
+}
+
+/// Dot product of the clipped ReLU of both accumulators with the output weights
+pub(super) fn output(us: &[i16], them: &[i16], weights: &[i16]) -> i32 {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is supported
+        return unsafe { avx2::output(us, them, weights) };
+    }
+    scalar::output(us, them, weights)
+}
+
+mod scalar {
+    use super::QA;
+
+    pub(super) fn add(accumulator: &mut [i16], weights: &[i16]) {
+        for (value, weight) in accumulator.iter_mut().zip(weights) {
+            *value = value.wrapping_add(*weight);
+        }
+    }
+
+    pub(super) fn sub(accumulator: &mut [i16], weights: &[i16]) {
+        for (value, weight) in accumulator.iter_mut().zip(weights) {
+            *value = value.wrapping_sub(*weight);
+        }
+    }
+
+    pub(super) fn output(us: &[i16], them: &[i16], weights: &[i16]) -> i32 {
+        us.iter()
+            .chain(them)
+            .zip(weights)
+            .map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32)
+            .fold(0, i32::wrapping_add)
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::QA;
+
+    /// Accumulators hold a multiple of 16 values, checked when the network is loaded
+    const LANES: usize = 16;
+
+    #[target_feature(enable = "avx2")]
+    pub(super) fn add(accumulator: &mut [i16], weights: &[i16]) {
+        assert!(weights.len() >= accumulator.len());
+        for i in (0..accumulator.len()).step_by(LANES) {
+            // SAFETY: i + LANES is in bounds of both slices
+            unsafe {
+                let value = accumulator.as_mut_ptr().add(i) as *mut __m256i;
+                let weight = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+                _mm256_storeu_si256(value, _mm256_add_epi16(_mm256_loadu_si256(value), weight));
+            }
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) fn sub(accumulator: &mut [i16], weights: &[i16]) {
+        assert!(weights.len() >= accumulator.len());
+        for i in (0..accumulator.len()).step_by(LANES) {
+            // SAFETY: i + LANES is in bounds of both slices
+            unsafe {
+                let value = accumulator.as_mut_ptr().add(i) as *mut __m256i;
+                let weight = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+                _mm256_storeu_si256(value, _mm256_sub_epi16(_mm256_loadu_si256(value), weight));
+            }
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) fn output(us: &[i16], them: &[i16], weights: &[i16]) -> i32 {
+        assert!(us.len() == them.len() && weights.len() >= 2 * us.len());
+        let (zero, max) = (_mm256_setzero_si256(), _mm256_set1_epi16(QA as i16));
+        let mut sum = _mm256_setzero_si256();
+        for (values, weights) in [(us, &weights[..us.len()]), (them, &weights[us.len()..])] {
+            for i in (0..values.len()).step_by(LANES) {
+                // SAFETY: i + LANES is in bounds of both slices
+                let (value, weight) = unsafe {
+                    (
+                        _mm256_loadu_si256(values.as_ptr().add(i) as *const __m256i),
+                        _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i),
+                    )
+                };
+                let value = _mm256_min_epi16(_mm256_max_epi16(value, zero), max);
+                sum = _mm256_add_epi32(sum, _mm256_madd_epi16(value, weight));
+            }
+        }
+        let mut lanes = [0i32; 8];
+        // SAFETY: lanes holds 8 i32
+        unsafe { _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum) };
+        lanes.into_iter().fold(0, i32::wrapping_add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operations() {
+        let weights: Vec<i16> = (0..64).map(|i| i * 37 - 1000).collect();
+        let mut accumulator: Vec<i16> = (0..32).map(|i| i * 11 - 50).collect();
+        let expected = scalar::output(&accumulator[..16], &accumulator[16..], &weights);
+        assert_eq!(
+            output(&accumulator[..16], &accumulator[16..], &weights),
+            expected
+        );
+
+        let initial = accumulator.clone();
+        add(&mut accumulator, &weights);
+        assert_eq!(accumulator[1], initial[1] + weights[1]);
+        sub(&mut accumulator, &weights);
+        assert_eq!(accumulator, initial);
+    }
+}
@@ -0,0 +1,352 @@
+use std::{fmt::Write, path::Path};
+
+use chess_core::{color::Color, square::Square, state::chess_board::PieceType};
+use serde::{Deserialize, Serialize};
+
+use super::{default_params::DEFAULT_PARAMS, phase_score::PhaseScore};
+
+/// Weights of the evaluation terms, in centipawns
+///
+/// Penalties are positive and subtracted from the score. Arrays indexed by piece type follow
+/// the order of `PieceType`.
+///
+/// Parameter files must set every field and nothing else, so that a renamed or forgotten weight
+/// is an error rather than a silent default.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EvalParams {
+    /// Value of each piece type in both phases, the king has no value
+    pub piece_values: [i32; 6],
+    /// Bonus of a piece depending on its square, from white's point of view with a1 first so
+    /// that the tables are indexed by square. Black pieces use the mirrored square.
+    #[serde(with = "tables")]
+    pub midgame_tables: [[i32; 64]; 6],
+    #[serde(with = "tables")]
+    pub endgame_tables: [[i32; 64]; 6],
+    /// Bonus per safe square attacked by a piece
+    pub mobility_weights: [PhaseScore; 6],
+    pub doubled_pawn_penalty: i32,
+    pub isolated_pawn_penalty: i32,
+    /// Bonus of a passed pawn by relative rank
+    pub passed_pawn_bonus: [PhaseScore; 8],
+    /// Bonus of a pawn standing next to a friendly pawn by relative rank
+    pub connected_pawn_bonus: [PhaseScore; 8],
+    pub protected_pawn_bonus: PhaseScore,
+    pub backward_pawn_penalty: PhaseScore,
+    /// King safety only counts in the midgame
+    pub pawn_shield_bonus: i32,
+    pub king_open_file_penalty: i32,
+    pub king_zone_attacker_penalty: i32,
+    pub rook_open_file_bonus: PhaseScore,
+    pub rook_semi_open_file_bonus: PhaseScore,
+    pub rook_on_seventh_bonus: PhaseScore,
+    pub bishop_pair_bonus: PhaseScore,
+    pub knight_outpost_bonus: PhaseScore,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        DEFAULT_PARAMS
+    }
+}
+
+impl EvalParams {
+    /// Loads a TOML or JSON parameter file, depending on its extension
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&contents),
+            Some("json") => Self::from_json(&contents),
+            _ => Err(format!(
+                "Unknown parameter file format {}, expected .toml or .json",
+                path.display()
+            )),
+        }
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, String> {
+        toml::from_str(source).map_err(|error| error.to_string())
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, String> {
+        serde_json::from_str(source).map_err(|error| error.to_string())
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Midgame and endgame bonuses of a piece of `color` on `square`
+    pub fn piece_square(&self, color: Color, piece: PieceType, square: Square) -> PhaseScore {
+        let index = match color {
+            Color::White => square.0 as usize,
+            Color::Black => square.mirror().0 as usize,
+        };
+        PhaseScore::new(
+            self.midgame_tables[piece as usize][index],
+            self.endgame_tables[piece as usize][index],
+        )
+    }
+
+    /// Every weight that changes the evaluation, for tuning: the king has no value, and pawns
+    /// never stand on the first and last ranks
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut i32> {
+        let phase_scores = self
+            .mobility_weights
+            .iter_mut()
+            .chain(self.passed_pawn_bonus.iter_mut())
+            .chain(self.connected_pawn_bonus.iter_mut())
+            .chain([
+                &mut self.protected_pawn_bonus,
+                &mut self.backward_pawn_penalty,
+                &mut self.rook_open_file_bonus,
+                &mut self.rook_semi_open_file_bonus,
+                &mut self.rook_on_seventh_bonus,
+                &mut self.bishop_pair_bonus,
+                &mut self.knight_outpost_bonus,
+            ])
+            .flat_map(|score| [&mut score.midgame, &mut score.endgame]);
+        let [piece_values @ .., _king] = &mut self.piece_values;
+        piece_values
+            .iter_mut()
+            .chain(table_values(&mut self.midgame_tables))
+            .chain(table_values(&mut self.endgame_tables))
+            .chain([
+                &mut self.doubled_pawn_penalty,
+                &mut self.isolated_pawn_penalty,
+                &mut self.pawn_shield_bonus,
+                &mut self.king_open_file_penalty,
+                &mut self.king_zone_attacker_penalty,
+            ])
+            .chain(phase_scores)
+    }
+
+    /// Source of the `default_params` module with these weights as the defaults
+    pub fn to_rust_source(&self) -> String {
+        const PIECE_NAMES: [&str; 6] = ["Pawn", "Knight", "Bishop", "Rook", "Queen", "King"];
+        let phase_score =
+            |score: &PhaseScore| format!("PhaseScore::new({}, {})", score.midgame, score.endgame);
+        let phase_scores = |scores: &[PhaseScore]| {
+            let lines: String = scores
+                .iter()
+                .map(|score| format!("        {},\n", phase_score(score)))
+                .collect();
+            format!("[\n{}    ]", lines)
+        };
+        let tables = |tables: &[[i32; 64]; 6]| {
+            let mut source = String::from("[\n");
+            for (table, name) in tables.iter().zip(PIECE_NAMES) {
+                writeln!(source, "        // {}\n        [", name).unwrap();
+                for row in table.chunks(8) {
+                    let row: String = row.iter().map(|value| format!("{:5},", value)).collect();
+                    writeln!(source, "           {}", row).unwrap();
+                }
+                writeln!(source, "        ],").unwrap();
+            }
+            source + "    ]"
+        };
+
+        let mut source = String::new();
+        source += "// Evaluation parameters\n";
+        source += "// Written by chess_tuner, which overwrites this file with the tuned weights.\n";
+        source += "\n";
+        source += "use super::{params::EvalParams, phase_score::PhaseScore};\n";
+        source += "\n";
+        source += "#[rustfmt::skip]\n";
+        source += "pub(super) const DEFAULT_PARAMS: EvalParams = EvalParams {\n";
+        let fields = [
+            ("piece_values", format!("{:?}", self.piece_values)),
+            ("midgame_tables", tables(&self.midgame_tables)),
+            ("endgame_tables", tables(&self.endgame_tables)),
+            ("mobility_weights", phase_scores(&self.mobility_weights)),
+            (
+                "doubled_pawn_penalty",
+                self.doubled_pawn_penalty.to_string(),
+            ),
+            (
+                "isolated_pawn_penalty",
+                self.isolated_pawn_penalty.to_string(),
+            ),
+            ("passed_pawn_bonus", phase_scores(&self.passed_pawn_bonus)),
+            (
+                "connected_pawn_bonus",
+                phase_scores(&self.connected_pawn_bonus),
+            ),
+            (
+                "protected_pawn_bonus",
+                phase_score(&self.protected_pawn_bonus),
+            ),
+            (
+                "backward_pawn_penalty",
+                phase_score(&self.backward_pawn_penalty),
+            ),
+            ("pawn_shield_bonus", self.pawn_shield_bonus.to_string()),
+            (
+                "king_open_file_penalty",
+                self.king_open_file_penalty.to_string(),
+            ),
+            (
+                "king_zone_attacker_penalty",
+                self.king_zone_attacker_penalty.to_string(),
+            ),
+            (
+                "rook_open_file_bonus",
+                phase_score(&self.rook_open_file_bonus),
+            ),
+            (
+                "rook_semi_open_file_bonus",
+                phase_score(&self.rook_semi_open_file_bonus),
+            ),
+            (
+                "rook_on_seventh_bonus",
+                phase_score(&self.rook_on_seventh_bonus),
+            ),
+            ("bishop_pair_bonus", phase_score(&self.bishop_pair_bonus)),
+            (
+                "knight_outpost_bonus",
+                phase_score(&self.knight_outpost_bonus),
+            ),
+        ];
+        for (name, value) in fields {
+            writeln!(source, "    {}: {},", name, value).unwrap();
+        }
+        source + "};\n"
+    }
+}
+
+/// Serde only implements arrays of up to 32 elements, tables are written as a list of lists
+mod tables {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub(super) fn serialize<S: Serializer>(
+        tables: &[[i32; 64]; 6],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(tables.iter().map(|table| &table[..]))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[[i32; 64]; 6], D::Error> {
+        let tables = Vec::<Vec<i32>>::deserialize(deserializer)?;
+        let length = tables.len();
+        let tables: Vec<[i32; 64]> = tables
+            .into_iter()
+            .map(|table| {
+                let length = table.len();
+                table.try_into().map_err(|_| {
+                    D::Error::custom(format!("expected 64 squares in a table, found {}", length))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        tables.try_into().map_err(|_| {
+            D::Error::custom(format!(
+                "expected 6 tables, one per piece, found {}",
+                length
+            ))
+        })
+    }
+}
+
+/// Entries of piece-square tables for squares the piece can stand on
+fn table_values(tables: &mut [[i32; 64]; 6]) -> impl Iterator<Item = &mut i32> {
+    tables.iter_mut().enumerate().flat_map(|(piece, table)| {
+        table
+            .iter_mut()
+            .enumerate()
+            .filter(move |(square, _)| {
+                piece != PieceType::Pawn as usize || (8..56).contains(square)
+            })
+            .map(|(_, value)| value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_source() {
+        // The defaults are written by to_rust_source
+        assert_eq!(
+            EvalParams::default().to_rust_source(),
+            include_str!("default_params.rs")
+        );
+    }
+
+    #[test]
+    fn test_values_mut() {
+        let params = &mut EvalParams::default();
+        assert_eq!(
+            params.values_mut().count(),
+            5 + 2 * (6 * 64 - 16) + 5 + 2 * (6 + 8 + 8 + 7)
+        );
+        for value in params.values_mut() {
+            *value = 1;
+        }
+        assert_eq!(params.knight_outpost_bonus, PhaseScore::new(1, 1));
+        assert_eq!(params.endgame_tables[5][63], 1);
+        assert_eq!(params.midgame_tables[0][8], 1);
+
+        // Weights which never change the evaluation are not tuned
+        let defaults = EvalParams::default();
+        assert_eq!(params.piece_values[5], defaults.piece_values[5]);
+        for square in (0..8).chain(56..64) {
+            assert_eq!(
+                params.midgame_tables[0][square],
+                defaults.midgame_tables[0][square]
+            );
+            assert_eq!(
+                params.endgame_tables[0][square],
+                defaults.endgame_tables[0][square]
+            );
+        }
+    }
+
+    #[test]
+    fn test_serialization() {
+        let params = EvalParams::default();
+        assert_eq!(EvalParams::from_toml(&params.to_toml()), Ok(params.clone()));
+        assert_eq!(EvalParams::from_json(&params.to_json()), Ok(params.clone()));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&EvalParams::default().to_json()).unwrap();
+        let object = json.as_object_mut().unwrap();
+
+        object.insert("queen_outpost_bonus".to_string(), 10.into());
+        let error = EvalParams::from_json(&json.to_string()).unwrap_err();
+        assert!(error.contains("unknown field"), "{}", error);
+
+        let object = json.as_object_mut().unwrap();
+        object.remove("queen_outpost_bonus");
+        object.remove("bishop_pair_bonus");
+        let error = EvalParams::from_json(&json.to_string()).unwrap_err();
+        assert!(error.contains("missing field"), "{}", error);
+
+        let object = json.as_object_mut().unwrap();
+        object.insert(
+            "bishop_pair_bonus".to_string(),
+            serde_json::json!({"midgame": 1}),
+        );
+        let error = EvalParams::from_json(&json.to_string()).unwrap_err();
+        assert!(error.contains("missing field"), "{}", error);
+
+        let object = json.as_object_mut().unwrap();
+        object.insert(
+            "bishop_pair_bonus".to_string(),
+            serde_json::json!({"midgame": 1, "endgame": 2}),
+        );
+        object["midgame_tables"][0].as_array_mut().unwrap().pop();
+        let error = EvalParams::from_json(&json.to_string()).unwrap_err();
+        assert!(error.contains("expected 64 squares"), "{}", error);
+    }
+}
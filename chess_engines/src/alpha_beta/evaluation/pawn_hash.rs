@@ -0,0 +1,87 @@
+// Pawn hash table
+// Pawns move rarely compared to the other pieces, so most positions of a search share their
+// pawn structure with many others. The pawn terms of the evaluation and the pawn bitboards the
+// other terms need are cached by the pawn hash kept by MakeUnmaker.
+//
+// Each search thread has its own small table: clones start empty and the entries are only
+// allocated on the first store, evaluations outside of a search never allocate it.
+
+use chess_core::{color::Color, state::bitboard::BitBoard};
+
+use super::phase_score::PhaseScore;
+
+/// Number of entries, about 750 kB
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+/// Evaluation of a pawn structure
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PawnEntry {
+    /// Pawn hash of the structure
+    pub key: u64,
+    /// Pawn terms of white minus those of black
+    pub score: PhaseScore,
+    /// Passed pawns of both colors
+    pub passed: BitBoard,
+    /// Squares that the pawns of each color can attack as they advance, by color
+    pub attack_spans: [BitBoard; 2],
+}
+
+impl PawnEntry {
+    pub fn attack_span(&self, color: Color) -> BitBoard {
+        self.attack_spans[color as usize]
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PawnHashTable {
+    entries: Vec<Option<PawnEntry>>,
+}
+
+impl Clone for PawnHashTable {
+    /// Tables are not shared, a clone is empty
+    fn clone(&self) -> Self {
+        PawnHashTable::default()
+    }
+}
+
+impl PawnHashTable {
+    pub fn get(&self, key: u64) -> Option<PawnEntry> {
+        let entry = (*self.entries.get(key as usize % PAWN_TABLE_SIZE)?)?;
+        (entry.key == key).then_some(entry)
+    }
+
+    /// Replaces the entry of the same index
+    pub fn insert(&mut self, entry: PawnEntry) {
+        if self.entries.is_empty() {
+            self.entries = vec![None; PAWN_TABLE_SIZE];
+        }
+        self.entries[entry.key as usize % PAWN_TABLE_SIZE] = Some(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pawn_hash_table() {
+        let entry = |key| PawnEntry {
+            key,
+            score: PhaseScore::new(10, 20),
+            passed: BitBoard::EMPTY,
+            attack_spans: [BitBoard::EMPTY; 2],
+        };
+        let mut table = PawnHashTable::default();
+        assert_eq!(table.get(0), None);
+        table.insert(entry(0));
+        table.insert(entry(5));
+        assert_eq!(table.get(0), Some(entry(0)));
+        assert_eq!(table.get(5), Some(entry(5)));
+        assert_eq!(table.get(PAWN_TABLE_SIZE as u64), None);
+
+        // Same index
+        table.insert(entry(PAWN_TABLE_SIZE as u64 + 5));
+        assert_eq!(table.get(5), None);
+        assert!(table.clone().get(PAWN_TABLE_SIZE as u64 + 5).is_none());
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    iter::Sum,
+    ops::{Add, AddAssign, Mul, Neg, Sub},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Phase of the starting position, the phase decreases as pieces are traded
+pub const MAX_PHASE: i32 = 24;
+
+/// Value of an evaluation term in the midgame and in the endgame, in centipawns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhaseScore {
+    pub midgame: i32,
+    pub endgame: i32,
+}
+
+impl PhaseScore {
+    pub const fn new(midgame: i32, endgame: i32) -> Self {
+        PhaseScore { midgame, endgame }
+    }
+
+    /// Interpolates between the midgame and endgame values, `phase` goes from MAX_PHASE in
+    /// the midgame to 0 in the endgame
+    pub fn taper(self, phase: i32) -> i32 {
+        (self.midgame * phase + self.endgame * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for PhaseScore {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        PhaseScore::new(self.midgame + rhs.midgame, self.endgame + rhs.endgame)
+    }
+}
+
+impl AddAssign for PhaseScore {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for PhaseScore {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl Neg for PhaseScore {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        PhaseScore::new(-self.midgame, -self.endgame)
+    }
+}
+
+impl Mul<i32> for PhaseScore {
+    type Output = Self;
+
+    fn mul(self, rhs: i32) -> Self {
+        PhaseScore::new(self.midgame * rhs, self.endgame * rhs)
+    }
+}
+
+impl Sum for PhaseScore {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(PhaseScore::default(), Add::add)
+    }
+}
@@ -1,173 +1,597 @@
-use chess_core::state::{bitboard::BitBoard, game_state::GameState};
+use chess_core::{
+    color::Color,
+    r#move::MoveGenerator,
+    state::{
+        bitboard::BitBoard,
+        chess_board::{ChessBoardSide, PieceType},
+        game_state::GameState,
+        material_key::MaterialKey,
+    },
+};
 
-use super::super::search::SearchContext;
+use super::{
+    Evaluator,
+    endgame::{self, SCALE_NORMAL},
+    masks::{
+        adjacent_files, attack_span, forward_ranks, forward_square, passed_pawn_mask, pawn_attacks,
+        relative_rank,
+    },
+    params::EvalParams,
+    pawn_hash::{PawnEntry, PawnHashTable},
+    phase_score::{MAX_PHASE, PhaseScore},
+    trace::{EvalTrace, TermTrace},
+};
 
-const DOUBLED_PAWN_COEF: i32 = 40;
-const ISOLATED_PAWN_COEF: i32 = 40;
-const MOBILITY_COEF: i32 = 5;
+/// Weight of each piece type in the game phase, pawns and kings do not count
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
 
-struct EvaluationContext<'a>(&'a GameState);
+/// Same value in both phases
+const fn unphased(value: i32) -> PhaseScore {
+    PhaseScore::new(value, value)
+}
+
+const fn midgame_only(value: i32) -> PhaseScore {
+    PhaseScore::new(value, 0)
+}
+
+/// Terms of the evaluation, each is computed for one side at a time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Term {
+    Material,
+    PieceSquares,
+    Mobility,
+    DoubledPawns,
+    IsolatedPawns,
+    PassedPawns,
+    ProtectedPawns,
+    ConnectedPawns,
+    BackwardPawns,
+    PawnShield,
+    KingOpenFiles,
+    KingZoneAttackers,
+    RookFiles,
+    RookOnSeventh,
+    BishopPair,
+    KnightOutposts,
+}
+
+impl Term {
+    const ALL: [Term; 16] = [
+        Term::Material,
+        Term::PieceSquares,
+        Term::Mobility,
+        Term::DoubledPawns,
+        Term::IsolatedPawns,
+        Term::PassedPawns,
+        Term::ProtectedPawns,
+        Term::ConnectedPawns,
+        Term::BackwardPawns,
+        Term::PawnShield,
+        Term::KingOpenFiles,
+        Term::KingZoneAttackers,
+        Term::RookFiles,
+        Term::RookOnSeventh,
+        Term::BishopPair,
+        Term::KnightOutposts,
+    ];
+
+    /// Terms that only depend on the pawns, cached in the pawn hash table
+    fn is_pawn_structure(self) -> bool {
+        matches!(
+            self,
+            Term::DoubledPawns
+                | Term::IsolatedPawns
+                | Term::PassedPawns
+                | Term::ProtectedPawns
+                | Term::ConnectedPawns
+                | Term::BackwardPawns
+        )
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::PieceSquares => "Piece-square tables",
+            Term::Mobility => "Mobility",
+            Term::DoubledPawns => "Doubled pawns",
+            Term::IsolatedPawns => "Isolated pawns",
+            Term::PassedPawns => "Passed pawns",
+            Term::ProtectedPawns => "Protected pawns",
+            Term::ConnectedPawns => "Connected pawns",
+            Term::BackwardPawns => "Backward pawns",
+            Term::PawnShield => "King pawn shield",
+            Term::KingOpenFiles => "King open files",
+            Term::KingZoneAttackers => "King zone attackers",
+            Term::RookFiles => "Rooks on open files",
+            Term::RookOnSeventh => "Rooks on 7th rank",
+            Term::BishopPair => "Bishop pair",
+            Term::KnightOutposts => "Knight outposts",
+        }
+    }
+}
+
+/// Pieces of the evaluated side and of its opponent
+#[derive(Clone, Copy)]
+struct Side<'a> {
+    color: Color,
+    own: &'a ChessBoardSide,
+    enemy: &'a ChessBoardSide,
+}
+
+struct EvaluationContext<'a> {
+    state: &'a GameState,
+    move_generator: &'a MoveGenerator,
+    params: &'a EvalParams,
+    /// From the pawn hash table, or computed for the position
+    pawn_entry: PawnEntry,
+}
+
+impl<'a> EvaluationContext<'a> {
+    fn new(
+        state: &'a GameState,
+        move_generator: &'a MoveGenerator,
+        params: &'a EvalParams,
+    ) -> Self {
+        let mut eval = EvaluationContext {
+            state,
+            move_generator,
+            params,
+            pawn_entry: pawn_bitboards(state),
+        };
+        eval.pawn_entry.score = eval.pawn_structure_score();
+        eval
+    }
+
+    /// Pawn terms of white minus those of black
+    fn pawn_structure_score(&self) -> PhaseScore {
+        let (white, black) = (self.side(Color::White), self.side(Color::Black));
+        Term::ALL
+            .into_iter()
+            .filter(|term| term.is_pawn_structure())
+            .map(|term| self.term_score(term, white) - self.term_score(term, black))
+            .sum()
+    }
+
+    fn side(&self, color: Color) -> Side<'_> {
+        let boards = &self.state.boards;
+        let (own, enemy) = match color {
+            Color::White => (&boards.white, &boards.black),
+            Color::Black => (&boards.black, &boards.white),
+        };
+        Side { color, own, enemy }
+    }
 
-impl EvaluationContext<'_> {
-    fn doubled_pawn_number(&self) -> i32 {
-        // - number of doubled pawns on active side + number of doubled on passive side
-        let (active_boards, passive_boards) = self.0.split_boards();
-        let (active_pawns, passive_pawns) = (active_boards.pawn, passive_boards.pawn);
+    fn occupation(&self) -> BitBoard {
+        self.state.boards.white.union() | self.state.boards.black.union()
+    }
 
+    fn doubled_pawn_number(&self, side: Side) -> i32 {
         // For each column, count the number of pawns in that column
-        let (mut active_doubled_pawns, mut passive_doubled_pawns) = (0, 0);
+        let mut doubled_pawns = 0;
         for col in 0..8 {
-            let column = BitBoard::file(col);
-            let (active_col, passive_col) = (column & active_pawns, column & passive_pawns);
-            if !active_col.is_empty() {
-                active_doubled_pawns += active_col.count_ones() as i32 - 1;
+            let column = BitBoard::file(col) & side.own.pawn;
+            if !column.is_empty() {
+                doubled_pawns += column.count_ones() as i32 - 1;
+            }
+        }
+        doubled_pawns
+    }
+
+    fn isolated_pawn_number(&self, side: Side) -> i32 {
+        // For each column, count the number of pawns in the adjacent columns
+        // Watch out for doubled isolated pawns, these should only be counted once (?)
+        let pawns = side.own.pawn;
+        let mut isolated_pawns = 0;
+        // Start with edge of board
+        if !(pawns & BitBoard::file(0)).is_empty() && (pawns & BitBoard::file(1)).is_empty() {
+            isolated_pawns += 1;
+        }
+        if !(pawns & BitBoard::file(7)).is_empty() && (pawns & BitBoard::file(6)).is_empty() {
+            isolated_pawns += 1;
+        }
+        for col in 1..7 {
+            if !(pawns & BitBoard::file(col)).is_empty()
+                && (pawns & BitBoard::file(col - 1)).is_empty()
+                && (pawns & BitBoard::file(col + 1)).is_empty()
+            {
+                isolated_pawns += 1;
             }
-            if !passive_col.is_empty() {
-                passive_doubled_pawns += passive_col.count_ones() as i32 - 1;
+        }
+        isolated_pawns
+    }
+
+    fn material_score(&self, side: Side) -> i32 {
+        side.own
+            .as_array()
+            .into_iter()
+            .map(|(board, piece)| {
+                self.params.piece_values[piece as usize] * board.count_ones() as i32
+            })
+            .sum()
+    }
+
+    /// MAX_PHASE in the opening down to 0 when only kings and pawns are left
+    fn game_phase(&self) -> i32 {
+        let (active_pieces, passive_pieces) = self.state.split_boards();
+        let phase: i32 = [active_pieces, passive_pieces]
+            .into_iter()
+            .flat_map(|side| side.as_array())
+            .map(|(board, piece)| PHASE_WEIGHTS[piece as usize] * board.count_ones() as i32)
+            .sum();
+        // Promotions can add material
+        phase.min(MAX_PHASE)
+    }
+
+    fn piece_square_score(&self, side: Side) -> PhaseScore {
+        let mut score = PhaseScore::default();
+        for (board, piece) in side.own.as_array() {
+            let mut board = *board;
+            while let Some(square) = board.pop_first_square() {
+                score += self.params.piece_square(side.color, piece, square);
             }
         }
+        score
+    }
 
-        passive_doubled_pawns - active_doubled_pawns
+    fn mobility_score(&self, side: Side) -> PhaseScore {
+        // Squares attacked by each piece, without the squares of friendly pieces and the
+        // squares attacked by enemy pawns
+        let occupation = self.occupation();
+        let safe_squares = !side.own.union() & !pawn_attacks(!side.color, side.enemy.pawn);
+        let mut score = PhaseScore::default();
+        for (board, piece) in side.own.as_array() {
+            let mut board = *board;
+            while let Some(square) = board.pop_first_square() {
+                let attacks = self
+                    .move_generator
+                    .attacks(piece, side.color, square, occupation);
+                let mobility = (attacks & safe_squares).count_ones() as i32;
+                score += self.params.mobility_weights[piece as usize] * mobility;
+            }
+        }
+        score
     }
 
-    fn isolated_pawn_number(&self) -> i32 {
-        // - number of isolated pawns on active side + number of isolated pawns on passive side
-        let (active_boards, passive_boards) = self.0.split_boards();
-        let (active_pawns, passive_pawns) = (active_boards.pawn, passive_boards.pawn);
+    fn passed_pawn_score(&self, side: Side) -> PhaseScore {
+        let mut score = PhaseScore::default();
+        let mut passed = side.own.pawn & self.pawn_entry.passed;
+        while let Some(square) = passed.pop_first_square() {
+            score += self.params.passed_pawn_bonus[relative_rank(side.color, square) as usize];
+        }
+        score
+    }
 
-        // For each column, count the number of pawns in the adjacent columns
-        // Watch out for doubled isolated pawns, these should only be counted once (?)
+    fn protected_pawn_number(&self, side: Side) -> i32 {
+        // Pawns defended by a friendly pawn
+        (side.own.pawn & pawn_attacks(side.color, side.own.pawn)).count_ones() as i32
+    }
 
-        let (mut active_isolated_pawns, mut passive_isolated_pawns) = (0, 0);
-        for (counter, pawns) in [
-            (&mut active_isolated_pawns, active_pawns),
-            (&mut passive_isolated_pawns, passive_pawns),
-        ] {
-            // Start with edge of board
-            if !(pawns & BitBoard::file(0)).is_empty() && (pawns & BitBoard::file(1)).is_empty() {
-                *counter += 1;
+    fn connected_pawn_score(&self, side: Side) -> PhaseScore {
+        // Pawns with a friendly pawn on the same rank and an adjacent file
+        let pawns = side.own.pawn;
+        let neighbours = ((pawns & !BitBoard::file(0)) >> 1) | ((pawns & !BitBoard::file(7)) << 1);
+        let mut connected = pawns & neighbours;
+        let mut score = PhaseScore::default();
+        while let Some(square) = connected.pop_first_square() {
+            score += self.params.connected_pawn_bonus[relative_rank(side.color, square) as usize];
+        }
+        score
+    }
+
+    fn backward_pawn_number(&self, side: Side) -> i32 {
+        // A pawn is backward when no friendly pawn on an adjacent file can support it, and an
+        // enemy pawn controls the square in front of it
+        let enemy_attacks = pawn_attacks(!side.color, side.enemy.pawn);
+        let mut pawns = side.own.pawn;
+        let mut backward = 0;
+        while let Some(square) = pawns.pop_first_square() {
+            let supporting =
+                adjacent_files(square.file()) & !forward_ranks(side.color, square.rank());
+            let stopped =
+                forward_square(side.color, square).is_some_and(|stop| enemy_attacks.get(stop));
+            if (supporting & side.own.pawn).is_empty() && stopped {
+                backward += 1;
             }
-            if !(pawns & BitBoard::file(7)).is_empty() && (pawns & BitBoard::file(6)).is_empty() {
-                *counter += 1;
+        }
+        backward
+    }
+
+    fn pawn_shield_number(&self, side: Side) -> i32 {
+        // Friendly pawns on the two ranks in front of the king and its files
+        let Some(king) = side.own.king.get_first_square() else {
+            return 0;
+        };
+        let files = BitBoard::file(king.file()) | adjacent_files(king.file());
+        let mut shield = BitBoard::EMPTY;
+        let mut square = king;
+        for _ in 0..2 {
+            let Some(next) = forward_square(side.color, square) else {
+                break;
+            };
+            shield |= BitBoard::rank(next.rank());
+            square = next;
+        }
+        (side.own.pawn & files & shield).count_ones() as i32
+    }
+
+    fn king_open_file_number(&self, side: Side) -> i32 {
+        // Files around the king without friendly pawns
+        let Some(king) = side.own.king.get_first_square() else {
+            return 0;
+        };
+        let first_file = king.file().saturating_sub(1);
+        let last_file = (king.file() + 1).min(7);
+        (first_file..=last_file)
+            .filter(|&file| (side.own.pawn & BitBoard::file(file)).is_empty())
+            .count() as i32
+    }
+
+    fn king_zone_attacker_number(&self, side: Side) -> i32 {
+        // Enemy pieces attacking the king or the squares around it
+        let Some(king) = side.own.king.get_first_square() else {
+            return 0;
+        };
+        let occupation = self.occupation();
+        let zone = self
+            .move_generator
+            .attacks(PieceType::King, side.color, king, occupation)
+            | BitBoard::from(king);
+        let mut attackers = 0;
+        for (board, piece) in side.enemy.as_array() {
+            if matches!(piece, PieceType::Pawn | PieceType::King) {
+                continue;
             }
-            for col in 1..7 {
-                if !(pawns & BitBoard::file(col)).is_empty()
-                    && (pawns & BitBoard::file(col - 1)).is_empty()
-                    && (pawns & BitBoard::file(col + 1)).is_empty()
+            let mut board = *board;
+            while let Some(square) = board.pop_first_square() {
+                if !(self
+                    .move_generator
+                    .attacks(piece, !side.color, square, occupation)
+                    & zone)
+                    .is_empty()
                 {
-                    *counter += 1;
+                    attackers += 1;
                 }
             }
         }
-        passive_isolated_pawns - active_isolated_pawns
+        attackers
+    }
+
+    fn rook_file_score(&self, side: Side) -> PhaseScore {
+        // Rooks on files without pawns, or without friendly pawns
+        let mut rooks = side.own.rook;
+        let mut score = PhaseScore::default();
+        while let Some(square) = rooks.pop_first_square() {
+            let file = BitBoard::file(square.file());
+            if (file & side.own.pawn).is_empty() {
+                score += if (file & side.enemy.pawn).is_empty() {
+                    self.params.rook_open_file_bonus
+                } else {
+                    self.params.rook_semi_open_file_bonus
+                };
+            }
+        }
+        score
+    }
+
+    fn rook_on_seventh_number(&self, side: Side) -> i32 {
+        let seventh = match side.color {
+            Color::White => BitBoard::rank(6),
+            Color::Black => BitBoard::rank(1),
+        };
+        (side.own.rook & seventh).count_ones() as i32
+    }
+
+    fn bishop_pair_number(&self, side: Side) -> i32 {
+        (side.own.bishop.count_ones() >= 2) as i32
+    }
+
+    fn knight_outpost_number(&self, side: Side) -> i32 {
+        // Knights in the enemy half defended by a pawn, that no enemy pawn can chase away
+        let defended = pawn_attacks(side.color, side.own.pawn);
+        let mut knights = side.own.knight;
+        let mut outposts = 0;
+        while let Some(square) = knights.pop_first_square() {
+            if (3..=5).contains(&relative_rank(side.color, square))
+                && defended.get(square)
+                && !self.pawn_entry.attack_span(!side.color).get(square)
+            {
+                outposts += 1;
+            }
+        }
+        outposts
+    }
+
+    /// Value of a term for one side, penalties are negative
+    fn term_score(&self, term: Term, side: Side) -> PhaseScore {
+        let params = self.params;
+        match term {
+            Term::Material => unphased(self.material_score(side)),
+            Term::PieceSquares => self.piece_square_score(side),
+            Term::Mobility => self.mobility_score(side),
+            Term::DoubledPawns => {
+                unphased(-params.doubled_pawn_penalty * self.doubled_pawn_number(side))
+            }
+            Term::IsolatedPawns => {
+                unphased(-params.isolated_pawn_penalty * self.isolated_pawn_number(side))
+            }
+            Term::PassedPawns => self.passed_pawn_score(side),
+            Term::ProtectedPawns => params.protected_pawn_bonus * self.protected_pawn_number(side),
+            Term::ConnectedPawns => self.connected_pawn_score(side),
+            Term::BackwardPawns => -params.backward_pawn_penalty * self.backward_pawn_number(side),
+            Term::PawnShield => {
+                midgame_only(params.pawn_shield_bonus * self.pawn_shield_number(side))
+            }
+            Term::KingOpenFiles => {
+                midgame_only(-params.king_open_file_penalty * self.king_open_file_number(side))
+            }
+            Term::KingZoneAttackers => midgame_only(
+                -params.king_zone_attacker_penalty * self.king_zone_attacker_number(side),
+            ),
+            Term::RookFiles => self.rook_file_score(side),
+            Term::RookOnSeventh => params.rook_on_seventh_bonus * self.rook_on_seventh_number(side),
+            Term::BishopPair => params.bishop_pair_bonus * self.bishop_pair_number(side),
+            Term::KnightOutposts => params.knight_outpost_bonus * self.knight_outpost_number(side),
+        }
     }
 
-    fn pawn_structure_score(&self) -> i32 {
-        DOUBLED_PAWN_COEF * self.doubled_pawn_number()
-            + ISOLATED_PAWN_COEF * self.isolated_pawn_number()
+    /// Terms that do not only depend on the pawns
+    fn piece_score(&self, side: Side) -> PhaseScore {
+        Term::ALL
+            .into_iter()
+            .filter(|term| !term.is_pawn_structure())
+            .map(|term| self.term_score(term, side))
+            .sum()
     }
 
-    fn board_material(active_pieces: BitBoard, passive_pieces: BitBoard, coef: i32) -> i32 {
-        let active_material = active_pieces.count_ones() as i32;
-        let passive_material = passive_pieces.count_ones() as i32;
-        coef * (active_material - passive_material)
+    /// Score of the side to move, without the specialized endgames
+    fn evaluate(&self, material_key: MaterialKey) -> i32 {
+        let active_color = self.state.flags.active_color();
+        let pawn_score = match active_color {
+            Color::White => self.pawn_entry.score,
+            Color::Black => -self.pawn_entry.score,
+        };
+        let score = self.piece_score(self.side(active_color))
+            - self.piece_score(self.side(!active_color))
+            + pawn_score;
+        let score = score.taper(self.game_phase());
+        let strong = if score >= 0 {
+            active_color
+        } else {
+            !active_color
+        };
+        score * endgame::scale_factor(self.state, material_key, strong, self.params) / SCALE_NORMAL
     }
 
-    fn material_score(&self) -> i32 {
-        let (active_pieces, passive_pieces) = self.0.split_boards();
-        Self::board_material(active_pieces.pawn, passive_pieces.pawn, 100)
-            + Self::board_material(active_pieces.knight, passive_pieces.knight, 300)
-            + Self::board_material(active_pieces.bishop, passive_pieces.bishop, 300)
-            + Self::board_material(active_pieces.rook, passive_pieces.rook, 500)
-            + Self::board_material(active_pieces.queen, passive_pieces.queen, 900)
+    fn trace(&self) -> EvalTrace {
+        let (white, black) = (self.side(Color::White), self.side(Color::Black));
+        let terms = Term::ALL
+            .into_iter()
+            .map(|term| TermTrace {
+                name: term.name(),
+                white: self.term_score(term, white),
+                black: self.term_score(term, black),
+            })
+            .collect();
+        let trace = EvalTrace::new(terms, self.game_phase());
+        let material_key = MaterialKey::from_state(self.state);
+        if let Some(endgame) = endgame::find(material_key, self.params) {
+            let score = match self.state.flags.active_color() {
+                Color::White => endgame.evaluate(self.state, self.params),
+                Color::Black => -endgame.evaluate(self.state, self.params),
+            };
+            return trace.with_endgame(endgame.name, score);
+        }
+        let strong = if trace.score >= 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        trace.with_scale_factor(endgame::scale_factor(
+            self.state,
+            material_key,
+            strong,
+            self.params,
+        ))
     }
 }
 
-impl SearchContext<'_> {
-    /// Mutable due to move list use but does not modify the state
-    pub fn is_checkmate(&mut self) -> bool {
-        if !self.move_generator.is_check(self.make_unmaker.state) {
-            return false;
-        }
-        self.move_list.new_ply();
-        let mut move_found = false;
-        self.move_generator
-            .get_pseudo_legal_moves(self.make_unmaker.state, &mut self.move_list);
-        for m in self.move_list.current_ply() {
-            self.make_unmaker.make_move(*m);
-            if self.move_generator.was_move_legal(self.make_unmaker.state) {
-                move_found = true;
-                self.make_unmaker.unmake_move(*m);
-                break;
+/// Passed pawns and attack spans of the position, without the score
+fn pawn_bitboards(state: &GameState) -> PawnEntry {
+    let mut entry = PawnEntry {
+        key: 0,
+        score: PhaseScore::default(),
+        passed: BitBoard::EMPTY,
+        attack_spans: [BitBoard::EMPTY; 2],
+    };
+    for color in [Color::White, Color::Black] {
+        let (own, enemy) = match color {
+            Color::White => (&state.boards.white, &state.boards.black),
+            Color::Black => (&state.boards.black, &state.boards.white),
+        };
+        let mut pawns = own.pawn;
+        while let Some(square) = pawns.pop_first_square() {
+            if (passed_pawn_mask(color, square) & enemy.pawn).is_empty() {
+                entry.passed |= square.into();
+            }
+            entry.attack_spans[color as usize] |= attack_span(color, square);
+        }
+    }
+    entry
+}
+
+/// Material, pawn structure, mobility, piece-square tables, king safety and piece placement
+#[derive(Clone, Default)]
+pub struct SimpleEvaluator {
+    move_generator: MoveGenerator,
+    pub params: EvalParams,
+    pawn_table: PawnHashTable,
+}
+
+impl SimpleEvaluator {
+    pub fn with_params(params: EvalParams) -> Self {
+        SimpleEvaluator {
+            move_generator: MoveGenerator::new(),
+            params,
+            pawn_table: PawnHashTable::default(),
+        }
+    }
+
+    /// Every term of the evaluation of `state` for both sides
+    pub fn eval_trace(&self, state: &GameState) -> EvalTrace {
+        EvaluationContext::new(state, &self.move_generator, &self.params).trace()
+    }
+}
+
+impl Evaluator for SimpleEvaluator {
+    fn evaluate(&mut self, state: &GameState) -> i32 {
+        let material_key = MaterialKey::from_state(state);
+        if let Some(endgame) = endgame::find(material_key, &self.params) {
+            return endgame.evaluate(state, &self.params);
+        }
+        EvaluationContext::new(state, &self.move_generator, &self.params).evaluate(material_key)
+    }
+
+    fn evaluate_with_keys(
+        &mut self,
+        state: &GameState,
+        pawn_hash: u64,
+        material_key: MaterialKey,
+    ) -> i32 {
+        if let Some(endgame) = endgame::find(material_key, &self.params) {
+            return endgame.evaluate(state, &self.params);
+        }
+        let eval = match self.pawn_table.get(pawn_hash) {
+            Some(pawn_entry) => EvaluationContext {
+                state,
+                move_generator: &self.move_generator,
+                params: &self.params,
+                pawn_entry,
+            },
+            None => {
+                let mut eval = EvaluationContext::new(state, &self.move_generator, &self.params);
+                eval.pawn_entry.key = pawn_hash;
+                self.pawn_table.insert(eval.pawn_entry);
+                eval
             }
-            self.make_unmaker.unmake_move(*m);
-        }
-        self.move_list.drop_current_ply();
-        !move_found
-    }
-
-    fn active_side_move_number(&mut self) -> i32 {
-        // TODO: use safe mobility?
-        self.move_list.new_ply();
-        // let mut move_number = 0;
-        self.move_generator
-            .get_pseudo_legal_moves(self.make_unmaker.state, &mut self.move_list);
-        let res = self.move_list.ply_size(self.move_list.ply_number());
-        self.move_list.drop_current_ply();
-        res as i32
-        // for m in self.move_list.get_current_ply() {
-        //     // FIXME: issue with same side en passant, this is a hack
-        //     if m.is_en_passant() {continue};
-        //     self.make_unmaker.make_move(*m);
-        //     if self.move_generator.was_move_legal(self.make_unmaker.state) {
-        //         move_number += 1;
-        //     }
-        //     self.make_unmaker.unmake_move(*m);
-        // }
-        // self.move_list.drop_current_ply();
-        // move_number
-    }
-
-    /// Mutable due to move list use but does not modify the state
-    fn mobility_score(&mut self) -> i32 {
-        // active mobility - passive mobility
-        let active_mobility = self.active_side_move_number();
-        self.make_unmaker.state.flags.toggle_active_color();
-        let passive_mobility = self.active_side_move_number();
-        self.make_unmaker.state.flags.toggle_active_color();
-        MOBILITY_COEF * (active_mobility - passive_mobility)
-    }
-
-    /// Mutable due to move list use but does not modify the state
-    pub fn evaluate(&mut self) -> i32 {
-        if self.is_checkmate() {
-            return -100000;
-        }
-        let eval = EvaluationContext(&self.make_unmaker.state);
-        eval.pawn_structure_score() + eval.material_score() + self.mobility_score()
+        };
+        eval.evaluate(material_key)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use chess_core::state::make_unmake::MakeUnmaker;
 
+    use super::super::default_params::DEFAULT_PARAMS;
     use super::*;
 
-    #[test]
-    fn test_is_checkmate() {
-        for (fen, result) in [
-            // starting position
-            (
-                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
-                false,
-            ),
-            // mate
-            // ("8/8/8/8/8/8/5KQ1/7k b - - 0 1", true),
-        ] {
-            let state = &mut GameState::from_fen(fen.to_string());
-            let mut search_context = SearchContext::new(state, None);
-            assert_eq!(search_context.is_checkmate(), result);
-        }
+    /// Value of a term for the side to move minus its value for the opponent
+    fn term_difference(state: &GameState, term: Term) -> PhaseScore {
+        let move_generator = &MoveGenerator::new();
+        let eval = EvaluationContext::new(state, move_generator, &DEFAULT_PARAMS);
+        let active_color = state.flags.active_color();
+        eval.term_score(term, eval.side(active_color))
+            - eval.term_score(term, eval.side(!active_color))
     }
 
     #[test]
@@ -195,9 +619,8 @@ mod tests {
             ),
         ] {
             let state = &mut GameState::from_fen(fen.to_string());
-            let eval = EvaluationContext(state);
-            let score = eval.material_score();
-            assert_eq!(score, result);
+            let score = term_difference(state, Term::Material);
+            assert_eq!(score, unphased(result));
         }
     }
 
@@ -212,23 +635,22 @@ mod tests {
             // white has doubled pawns, white to move
             (
                 "rnbqkbnr/pppppppp/8/8/8/1P6/1PPPPPPP/RNBQKBNR w KQkq - 0 1",
-                -DOUBLED_PAWN_COEF,
+                -DEFAULT_PARAMS.doubled_pawn_penalty,
             ),
             // black has doubled pawns, white to move
             (
                 "rnbqkbnr/1ppppppp/1p6/8/8/8/PPPPPPPP/RNBQKB1R w KQkq - 0 1",
-                DOUBLED_PAWN_COEF,
+                DEFAULT_PARAMS.doubled_pawn_penalty,
             ),
             // white has tripled pawns, black to move
             (
                 "rnbqkbnr/pppppppp/8/8/2P5/2P5/11PPPPPP/RNBQKBNR b KQkq - 0 1",
-                2 * DOUBLED_PAWN_COEF,
+                2 * DEFAULT_PARAMS.doubled_pawn_penalty,
             ),
         ] {
             let state = &mut GameState::from_fen(fen.to_string());
-            let eval = EvaluationContext(state);
-            let score = eval.doubled_pawn_number() * DOUBLED_PAWN_COEF;
-            assert_eq!(score, result, "FEN: {}", fen);
+            let score = term_difference(state, Term::DoubledPawns);
+            assert_eq!(score, unphased(result), "FEN: {}", fen);
         }
     }
 
@@ -243,18 +665,17 @@ mod tests {
             // white has isolated pawns on the side, white to move
             (
                 "rnbqkbnr/pppppppp/8/8/8/8/P1PPPPPP/RNBQKBNR w KQkq - 0 1",
-                -ISOLATED_PAWN_COEF,
+                -DEFAULT_PARAMS.isolated_pawn_penalty,
             ),
             // white has isolated pawns on the middle, black to move
             (
                 "rnbqkbnr/pppppppp/8/8/8/8/1P1PPPPP/RNBQKBNR b KQkq - 0 1",
-                ISOLATED_PAWN_COEF,
+                DEFAULT_PARAMS.isolated_pawn_penalty,
             ),
         ] {
             let state = &mut GameState::from_fen(fen.to_string());
-            let eval = EvaluationContext(state);
-            let score = eval.isolated_pawn_number() * ISOLATED_PAWN_COEF;
-            assert_eq!(score, result, "FEN: {}", fen);
+            let score = term_difference(state, Term::IsolatedPawns);
+            assert_eq!(score, unphased(result), "FEN: {}", fen);
         }
     }
 
@@ -264,18 +685,404 @@ mod tests {
             // starting position
             (
                 "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
-                0,
+                PhaseScore::default(),
             ),
-            // white has more mobility, white to move
+            // the a1 rook attacks a2 to a7, but a6 is attacked by the b7 pawn
             (
                 "rnbqkbnr/pppppppp/8/8/8/8/1PPPPPPP/RNBQKBNR w KQkq - 0 1",
-                4 * MOBILITY_COEF,
+                DEFAULT_PARAMS.mobility_weights[PieceType::Rook as usize] * 5,
+            ),
+            // c5 is attacked by the d6 pawn, pawns and kings do not count, black to move
+            (
+                "4k3/8/3p4/8/4N3/8/8/4K3 b - - 0 1",
+                DEFAULT_PARAMS.mobility_weights[PieceType::Knight as usize] * -7,
             ),
         ] {
-            let state = &mut GameState::from_fen(fen.to_string());
-            let mut search_context = SearchContext::new(state, None);
-            let score = search_context.mobility_score();
+            let state = &GameState::from_fen(fen.to_string());
+            let score = term_difference(state, Term::Mobility);
             assert_eq!(score, result, "FEN: {}", fen);
         }
     }
+
+    #[test]
+    fn test_game_phase() {
+        for (fen, result) in [
+            // starting position
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                MAX_PHASE,
+            ),
+            // queens traded
+            (
+                "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1",
+                MAX_PHASE - 8,
+            ),
+            // rook endgame
+            ("4k3/pp3r2/8/8/8/8/PP6/4KR2 b - - 0 1", 4),
+            // pawn endgame
+            ("4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1", 0),
+            // extra queens from promotions
+            ("QQQ1k3/8/8/8/8/8/8/RNBQKBNR w - - 0 1", MAX_PHASE),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            let move_generator = &MoveGenerator::new();
+            let eval = EvaluationContext::new(state, move_generator, &DEFAULT_PARAMS);
+            assert_eq!(eval.game_phase(), result, "FEN: {}", fen);
+        }
+    }
+
+    #[test]
+    fn test_piece_square_evaluation() {
+        for (fen, midgame, endgame) in [
+            // starting position
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                0,
+                0,
+            ),
+            // central pawns advanced by both sides
+            (
+                "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1",
+                0,
+                0,
+            ),
+            // white knight in the center, black knight on the rim, black to move
+            ("n3k3/8/8/8/4N3/8/8/4K3 b - - 0 1", -70, -70),
+            // castled white king, centralized black king
+            ("8/8/8/3k4/8/8/8/6K1 w - - 0 1", 70, -70),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::PieceSquares),
+                PhaseScore::new(midgame, endgame),
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_tapered_evaluation() {
+        let state = &GameState::from_fen("8/8/8/3k4/8/8/8/6K1 w - - 0 1".to_string());
+        let move_generator = &MoveGenerator::new();
+        let eval = EvaluationContext::new(state, move_generator, &DEFAULT_PARAMS);
+        // Only the endgame score counts without pieces
+        assert_eq!(PhaseScore::new(70, -70).taper(eval.game_phase()), -70);
+
+        let state = &GameState::from_fen("r3k3/8/8/8/8/8/8/4K2R w - - 0 1".to_string());
+        let move_generator = &MoveGenerator::new();
+        let eval = EvaluationContext::new(state, move_generator, &DEFAULT_PARAMS);
+        assert_eq!(
+            PhaseScore::new(70, -70).taper(eval.game_phase()),
+            (70 * 4 - 70 * 20) / MAX_PHASE
+        );
+    }
+
+    #[test]
+    fn test_passed_pawn_evaluation() {
+        for (fen, result) in [
+            // white passed pawn on d5
+            (
+                "4k3/8/8/3P4/8/8/8/4K3 w - - 0 1",
+                DEFAULT_PARAMS.passed_pawn_bonus[4],
+            ),
+            // the e6 pawn stops the d5 pawn and the other way around
+            ("4k3/8/4p3/3P4/8/8/8/4K3 w - - 0 1", PhaseScore::default()),
+            // black passed pawn on a2, black to move
+            (
+                "4k3/8/8/8/8/8/p7/4K3 b - - 0 1",
+                DEFAULT_PARAMS.passed_pawn_bonus[6],
+            ),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::PassedPawns),
+                result,
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_protected_pawn_evaluation() {
+        for (fen, result) in [
+            // the c3 pawn protects the d4 pawn
+            ("4k3/8/8/8/3P4/2P5/8/4K3 w - - 0 1", 1),
+            ("4k3/8/8/8/3P4/2P5/8/4K3 b - - 0 1", -1),
+            // pawns side by side do not protect each other
+            ("4k3/8/8/8/2PP4/8/8/4K3 w - - 0 1", 0),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::ProtectedPawns),
+                DEFAULT_PARAMS.protected_pawn_bonus * result,
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_connected_pawn_evaluation() {
+        for (fen, result) in [
+            // d4 and e4 are connected
+            (
+                "4k3/8/8/8/3PP3/8/8/4K3 w - - 0 1",
+                DEFAULT_PARAMS.connected_pawn_bonus[3] * 2,
+            ),
+            // a pawn on the h-file is not next to a pawn on the a-file
+            ("4k3/8/8/8/P6P/8/8/4K3 w - - 0 1", PhaseScore::default()),
+            // black pawns on b3 and c3 are close to promotion
+            (
+                "4k3/8/8/8/8/1pp5/8/4K3 w - - 0 1",
+                DEFAULT_PARAMS.connected_pawn_bonus[5] * -2,
+            ),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::ConnectedPawns),
+                result,
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_backward_pawn_evaluation() {
+        for (fen, result) in [
+            // the d3 and c5 pawns are both backward
+            ("4k3/8/8/2p5/8/3P4/8/4K3 w - - 0 1", 0),
+            // the b6 pawn can support the c5 pawn
+            ("4k3/8/1p6/2p5/8/3P4/8/4K3 w - - 0 1", -1),
+            // the e2 pawn can support the d3 pawn
+            ("4k3/8/1p6/2p5/8/3P4/4P3/4K3 b - - 0 1", 0),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::BackwardPawns),
+                DEFAULT_PARAMS.backward_pawn_penalty * result,
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_pawn_shield_evaluation() {
+        for (fen, result) in [
+            ("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1", 0),
+            // the g5 pawn is too far from the king
+            ("6k1/5p1p/8/6p1/8/6P1/5P1P/6K1 w - - 0 1", 1),
+            // the king has left its pawns
+            ("6k1/5ppp/8/8/8/8/5PPP/3K4 w - - 0 1", -3),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::PawnShield),
+                midgame_only(DEFAULT_PARAMS.pawn_shield_bonus * result),
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_king_open_file_evaluation() {
+        for (fen, result) in [
+            ("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1", 0),
+            // the g-file is open in front of the black king
+            ("6k1/5p1p/8/8/8/8/5PPP/6K1 w - - 0 1", 1),
+            // the white king on the edge only has two files
+            ("6k1/5ppp/8/8/8/8/8/7K b - - 0 1", 2),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::KingOpenFiles),
+                midgame_only(DEFAULT_PARAMS.king_open_file_penalty * result),
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_king_zone_attacker_evaluation() {
+        for (fen, result) in [
+            ("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1", 0),
+            // the h6 knight attacks f7 and g8
+            ("6k1/5ppp/7N/8/8/8/5PPP/6K1 w - - 0 1", 1),
+            // the b7 bishop attacks g2 through the long diagonal, the d8 rook is blocked
+            ("3r2k1/1b3ppp/8/8/8/3P4/5PPP/6K1 w - - 0 1", -1),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::KingZoneAttackers),
+                midgame_only(DEFAULT_PARAMS.king_zone_attacker_penalty * result),
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_rook_file_evaluation() {
+        for (fen, result) in [
+            // semi-open a-file for white
+            (
+                "4k3/pp6/8/8/8/8/1P6/R3K3 w - - 0 1",
+                DEFAULT_PARAMS.rook_semi_open_file_bonus,
+            ),
+            // open a-file for white, black to move
+            (
+                "4k3/8/8/8/8/8/8/R3K3 b - - 0 1",
+                DEFAULT_PARAMS.rook_open_file_bonus * -1,
+            ),
+            // a friendly pawn closes the file
+            ("4k3/8/8/8/8/8/P7/R3K3 w - - 0 1", PhaseScore::default()),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::RookFiles),
+                result,
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_rook_on_seventh_evaluation() {
+        for (fen, result) in [
+            ("4k3/R7/8/8/8/8/8/4K3 w - - 0 1", 1),
+            ("4k3/8/8/8/8/8/r7/4K3 w - - 0 1", -1),
+            // the seventh rank of white is the second rank of black
+            ("4k3/r7/8/8/8/8/8/4K3 b - - 0 1", 0),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::RookOnSeventh),
+                DEFAULT_PARAMS.rook_on_seventh_bonus * result,
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_bishop_pair_evaluation() {
+        for (fen, result) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                0,
+            ),
+            ("2b1kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", -1),
+            ("2b1k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", 1),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::BishopPair),
+                DEFAULT_PARAMS.bishop_pair_bonus * result,
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_knight_outpost_evaluation() {
+        for (fen, result) in [
+            // d5 knight defended by the c4 pawn
+            ("4k3/8/8/3N4/2P5/8/8/4K3 w - - 0 1", 1),
+            // the e7 pawn can chase it away
+            ("4k3/4p3/8/3N4/2P5/8/8/4K3 w - - 0 1", 0),
+            // undefended
+            ("4k3/8/8/3N4/8/8/8/4K3 w - - 0 1", 0),
+            // black knight on e4 defended by the d5 pawn, white to move
+            ("4k3/8/8/3p4/4n3/8/8/4K3 w - - 0 1", -1),
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            assert_eq!(
+                term_difference(state, Term::KnightOutposts),
+                DEFAULT_PARAMS.knight_outpost_bonus * result,
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_trace() {
+        let evaluator = &mut SimpleEvaluator::default();
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 4 4",
+            "8/5pk1/6p1/3P4/2R5/6P1/5PK1/1r6 b - - 0 40",
+            // Specialized endgame, and opposite colored bishops
+            "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1",
+            "8/4k1b1/8/8/4P3/2PB4/8/4K3 b - - 0 1",
+        ] {
+            let state = &GameState::from_fen(fen.to_string());
+            let trace = evaluator.eval_trace(state);
+            let score = match state.flags.active_color() {
+                Color::White => trace.score,
+                Color::Black => -trace.score,
+            };
+            assert_eq!(evaluator.evaluate(state), score, "FEN: {}", fen);
+        }
+
+        let state = &GameState::from_fen(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+        );
+        let trace = evaluator.eval_trace(state);
+        assert_eq!(trace.terms.len(), Term::ALL.len());
+        assert_eq!(trace.terms[0].white, unphased(3900));
+        assert_eq!(trace.terms[0].black, unphased(3900));
+        assert_eq!((trace.phase, trace.score), (MAX_PHASE, 0));
+        let table = trace.to_string();
+        assert!(table.contains("King zone attackers"), "{}", table);
+        assert_eq!(table.lines().count(), Term::ALL.len() + 8, "{}", table);
+
+        let state = &GameState::from_fen("8/4k1b1/8/8/4P3/2PB4/8/4K3 w - - 0 1".to_string());
+        assert!(
+            evaluator
+                .eval_trace(state)
+                .to_string()
+                .contains("Scale factor: 24/64")
+        );
+        let state = &GameState::from_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1".to_string());
+        assert_eq!(evaluator.eval_trace(state).endgame, Some("KPvK"));
+    }
+
+    #[test]
+    fn test_pawn_hash() {
+        let move_generator = &MoveGenerator::new();
+        let evaluator = &mut SimpleEvaluator::default();
+        for fen in [
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let state = &mut GameState::from_fen(fen.to_string());
+            let make_unmaker = &mut MakeUnmaker::new(state);
+            let mut moves = Vec::new();
+            move_generator.get_pseudo_legal_moves(make_unmaker.state, &mut moves);
+            // Twice, the second time from the table
+            for m in moves.iter().chain(&moves) {
+                make_unmaker.make_move(*m);
+                let (pawn_hash, material_key) = (make_unmaker.pawn_hash, make_unmaker.material_key);
+                assert_eq!(
+                    evaluator.evaluate_with_keys(make_unmaker.state, pawn_hash, material_key),
+                    evaluator.evaluate(make_unmaker.state),
+                    "FEN: {}, move: {}",
+                    fen,
+                    m
+                );
+                assert!(evaluator.pawn_table.get(pawn_hash).is_some());
+                make_unmaker.unmake_move(*m);
+            }
+        }
+    }
 }
@@ -0,0 +1,103 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use super::{
+    endgame::SCALE_NORMAL,
+    phase_score::{MAX_PHASE, PhaseScore},
+};
+
+/// Value of one evaluation term for each side
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TermTrace {
+    pub name: &'static str,
+    pub white: PhaseScore,
+    pub black: PhaseScore,
+}
+
+impl TermTrace {
+    pub fn total(&self) -> PhaseScore {
+        self.white - self.black
+    }
+}
+
+/// Breakdown of a static evaluation, every score is from the point of view of white
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EvalTrace {
+    pub terms: Vec<TermTrace>,
+    /// From MAX_PHASE with all pieces on the board down to 0
+    pub phase: i32,
+    /// Sum of the terms, in both phases
+    pub total: PhaseScore,
+    /// Specialized evaluation of the material configuration that replaces the terms
+    pub endgame: Option<&'static str>,
+    /// Out of SCALE_NORMAL, reduces the score of drawish endings
+    pub scale_factor: i32,
+    /// Total interpolated by game phase and scaled, or the score of the endgame evaluation
+    pub score: i32,
+}
+
+impl EvalTrace {
+    pub fn new(terms: Vec<TermTrace>, phase: i32) -> Self {
+        let total: PhaseScore = terms.iter().map(TermTrace::total).sum();
+        EvalTrace {
+            terms,
+            phase,
+            total,
+            endgame: None,
+            scale_factor: SCALE_NORMAL,
+            score: total.taper(phase),
+        }
+    }
+
+    pub fn with_scale_factor(mut self, scale_factor: i32) -> Self {
+        self.score = self.score * scale_factor / SCALE_NORMAL;
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    pub fn with_endgame(mut self, name: &'static str, score: i32) -> Self {
+        self.endgame = Some(name);
+        self.score = score;
+        self
+    }
+}
+
+impl Display for EvalTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let separator = format!("{:-<22}+{:-<14}+{:-<14}+{:-<14}", "", "", "", "");
+        let header = format!(
+            "{:<22}|{:^14}|{:^14}|{:^14}",
+            "Term", "White", "Black", "Total"
+        );
+        writeln!(f, "{}", header.trim_end())?;
+        writeln!(
+            f,
+            "{:<22}|{:>7}{:>7}|{:>7}{:>7}|{:>7}{:>7}",
+            "", "MG", "EG", "MG", "EG", "MG", "EG"
+        )?;
+        writeln!(f, "{}", separator)?;
+        let row = |f: &mut std::fmt::Formatter<'_>, name: &str, scores: [PhaseScore; 3]| {
+            write!(f, "{:<22}", name)?;
+            for score in scores {
+                write!(f, "|{:>7}{:>7}", score.midgame, score.endgame)?;
+            }
+            writeln!(f)
+        };
+        for term in &self.terms {
+            row(f, term.name, [term.white, term.black, term.total()])?;
+        }
+        writeln!(f, "{}", separator)?;
+        let white: PhaseScore = self.terms.iter().map(|term| term.white).sum();
+        let black: PhaseScore = self.terms.iter().map(|term| term.black).sum();
+        row(f, "Total", [white, black, self.total])?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {}/{}", self.phase, MAX_PHASE)?;
+        if let Some(endgame) = self.endgame {
+            writeln!(f, "Endgame: {}, replaces the terms", endgame)?;
+        } else if self.scale_factor != SCALE_NORMAL {
+            writeln!(f, "Scale factor: {}/{}", self.scale_factor, SCALE_NORMAL)?;
+        }
+        write!(f, "Score: {} (white side)", self.score)
+    }
+}
@@ -0,0 +1,114 @@
+// Lazy SMP
+// Helper threads run their own iterative deepening from the same root and only communicate
+// with the main thread through the shared transposition table. Their results are thrown
+// away: they fill the table with entries that cut the main search short.
+// Odd helpers start one ply deeper than even ones so that the threads do not all search the
+// same tree in the same order. With a single thread no helper is started, and the search is
+// deterministic.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+};
+
+use super::{
+    evaluation::Evaluator,
+    search::{AnalysisLine, SearchContext},
+    search_limits::{MAX_DEPTH, SearchLimits},
+    search_stats::SearchStats,
+};
+
+impl<E: Evaluator> SearchContext<'_, E> {
+    /// Iterative deepening on `self.threads` threads, the calling thread being the main thread
+    pub(super) fn lazy_smp(&mut self, limits: &SearchLimits) -> Vec<AnalysisLine> {
+        self.transpos.new_search();
+        self.nodes = 0;
+        self.stats = SearchStats::default();
+        self.evaluator.reset(self.make_unmaker.state);
+        self.root_moves = limits.search_moves.clone();
+        if self.threads <= 1 {
+            return self.deepen(limits);
+        }
+
+        let stop = AtomicBool::new(false);
+        let root = *self.make_unmaker.state;
+        let transpos = self.transpos;
+        let start_depth = self.max_depth;
+        let params = self.params;
+        let evaluators: Vec<E> = (1..self.threads).map(|_| self.evaluator.clone()).collect();
+
+        thread::scope(|scope| {
+            for (id, evaluator) in (1..self.threads).zip(evaluators) {
+                let stop = &stop;
+                let root_moves = self.root_moves.clone();
+                scope.spawn(move || {
+                    let mut state = root;
+                    let depth = start_depth + (id % 2) as u8;
+                    let mut helper =
+                        SearchContext::with_evaluator(&mut state, transpos, Some(depth), evaluator);
+                    helper.stop = Some(stop);
+                    helper.params = params;
+                    helper.root_moves = root_moves;
+                    helper.help();
+                });
+            }
+            let result = self.deepen(limits);
+            stop.store(true, Ordering::Relaxed);
+            result
+        })
+    }
+
+    fn help(&mut self) {
+        let mut pv = Vec::new();
+        while !self.is_stopped() && self.max_depth <= MAX_DEPTH {
+            (_, pv) = self.search(&pv);
+            self.max_depth += 1;
+        }
+    }
+
+    /// Helper threads are stopped by the main thread, the main thread by the caller or by its
+    /// limits
+    pub(super) fn is_stopped(&self) -> bool {
+        self.interrupted || self.stop.is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chess_core::{
+        r#move::{Move, MoveCode},
+        square::Square,
+    };
+
+    use super::super::search::tests::TestPosition;
+
+    // White can take the queen with the bishop
+    const FEN: &str = "rnb1kbnr/pppp1ppp/8/4p1q1/4P3/3P4/PPP2PPP/RNBQKBNR w KQkq - 0 1";
+
+    fn search(threads: usize) -> (i32, Vec<Move>) {
+        let position = &mut TestPosition::new(FEN);
+        let mut search_ctx = position.search_ctx();
+        search_ctx.threads = threads;
+        search_ctx.iterative_deepen_to_depth(4)
+    }
+
+    #[test]
+    fn test_single_thread_is_deterministic() {
+        let (score, pv) = search(1);
+        assert_eq!(search(1), (score, pv.clone()));
+        assert_eq!(
+            pv.first(),
+            Some(&Move::new(Square(2), Square(38), MoveCode::Capture))
+        );
+    }
+
+    #[test]
+    fn test_lazy_smp() {
+        let (score, pv) = search(4);
+        assert!(score > 500, "{}", score);
+        assert_eq!(
+            pv.first(),
+            Some(&Move::new(Square(2), Square(38), MoveCode::Capture))
+        );
+    }
+}
@@ -1,3 +1,11 @@
-mod evaluation;
+pub mod evaluation;
+mod lazy_smp;
+mod move_ordering;
+pub mod opening_book;
+mod pv_table;
 pub mod search;
-mod transposition_table;
+pub mod search_limits;
+pub mod search_params;
+pub mod search_stats;
+pub mod skill;
+pub mod transposition_table;
@@ -0,0 +1,366 @@
+// Move ordering
+// Alpha-beta cuts off sooner when the best move is searched first. Moves get a score when a
+// ply is generated, and are picked best first one at a time (MoveList::pick_move).
+// In order: the move of the previous principal variation on its line, the hash move, captures
+// that do not lose material by MVV-LVA, the two killer moves of the ply, the countermove of the
+// previous move, the other quiet moves by history, and captures losing material.
+// Quiet moves learn from the cutoffs of the search: killers are the last quiet moves that
+// failed high at the same ply, the countermove is the last quiet move that failed high after
+// the same previous move, and the histories sum bonuses and penalties of every cutoff.
+
+use chess_core::{
+    color::Color,
+    r#move::{MAX_PLY, Move, MoveCode},
+    square::Square,
+    state::{
+        bitboard::BitBoard,
+        chess_board::{ChessBoardSide, PieceType},
+    },
+};
+
+use super::{evaluation::Evaluator, search::SearchContext};
+
+const PV_MOVE_SCORE: i32 = 1 << 30;
+const HASH_MOVE_SCORE: i32 = PV_MOVE_SCORE - 1;
+const GOOD_CAPTURE_SCORE: i32 = 1 << 28;
+const KILLER_SCORE: i32 = 1 << 27;
+const COUNTERMOVE_SCORE: i32 = 1 << 26;
+const BAD_CAPTURE_SCORE: i32 = -(1 << 28);
+
+/// Quiet moves recorded per ply, more than any position has
+const MAX_QUIETS: usize = 256;
+
+/// History scores stay within [-MAX_HISTORY, MAX_HISTORY]
+const MAX_HISTORY: i32 = 16384;
+const MAX_HISTORY_BONUS: i32 = 1200;
+
+/// Values used by static exchange evaluation, the king cannot be exchanged
+pub(super) const SEE_VALUES: [i32; 6] = [100, 300, 300, 500, 900, 20000];
+
+pub(super) struct MoveOrdering {
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    /// Butterfly history, indexed by color, from and to squares
+    history: Vec<i32>,
+    /// Refutation of a move, indexed by its from and to squares
+    countermoves: Vec<Option<Move>>,
+    /// History of a move following the previous move, indexed by the piece and destination of
+    /// both moves
+    continuation_history: Vec<i32>,
+    /// Move played at each ply of the current line with the moved piece, None for a null move
+    stack: [Option<(Move, PieceType)>; MAX_PLY],
+    /// Quiet moves searched at each ply with the moved piece, MAX_QUIETS per ply, so that the
+    /// search records them without allocating
+    quiets: Vec<(Move, PieceType)>,
+    quiet_counts: [usize; MAX_PLY],
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        MoveOrdering {
+            killers: [[None; 2]; MAX_PLY],
+            history: vec![0; 2 * 64 * 64],
+            countermoves: vec![None; 64 * 64],
+            continuation_history: vec![0; 6 * 64 * 6 * 64],
+            stack: [None; MAX_PLY],
+            quiets: vec![
+                (
+                    Move::new(Square(0), Square(0), MoveCode::QuietMove),
+                    PieceType::Pawn
+                );
+                MAX_PLY * MAX_QUIETS
+            ],
+            quiet_counts: [0; MAX_PLY],
+        }
+    }
+}
+
+fn history_index(color: Color, m: Move) -> usize {
+    (color as usize * 64 + m.from().0 as usize) * 64 + m.to().0 as usize
+}
+
+fn continuation_index(previous: (Move, PieceType), m: Move, piece: PieceType) -> usize {
+    let (previous_move, previous_piece) = previous;
+    ((previous_piece as usize * 64 + previous_move.to().0 as usize) * 6 + piece as usize) * 64
+        + m.to().0 as usize
+}
+
+/// History gravity: the closer an entry is to the bounds, the less a bonus moves it
+fn apply_bonus(entry: &mut i32, bonus: i32) {
+    *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+}
+
+/// Most valuable victim first, least valuable attacker first among equal victims
+fn mvv_lva(victim: PieceType, attacker: PieceType) -> i32 {
+    16 * victim as i32 - attacker as i32
+}
+
+impl MoveOrdering {
+    fn previous_move(&self, ply: u8) -> Option<(Move, PieceType)> {
+        if ply == 0 {
+            None
+        } else {
+            self.stack[ply as usize - 1]
+        }
+    }
+
+    /// Records the move played at `ply`, None for a null move
+    pub(super) fn push(&mut self, ply: u8, m: Option<(Move, PieceType)>) {
+        self.stack[ply as usize] = m;
+    }
+
+    /// Forgets the quiet moves searched at `ply`, when a node is entered
+    pub(super) fn clear_quiets(&mut self, ply: u8) {
+        self.quiet_counts[ply as usize] = 0;
+    }
+
+    /// Records a quiet move searched at `ply`, its history is updated on a cutoff
+    pub(super) fn push_quiet(&mut self, ply: u8, m: (Move, PieceType)) {
+        let count = &mut self.quiet_counts[ply as usize];
+        if *count < MAX_QUIETS {
+            self.quiets[ply as usize * MAX_QUIETS + *count] = m;
+            *count += 1;
+        }
+    }
+
+    fn quiet_score(&self, ply: u8, color: Color, m: Move, piece: PieceType) -> i32 {
+        let killers = &self.killers[ply as usize];
+        if killers[0] == Some(m) {
+            return KILLER_SCORE;
+        }
+        if killers[1] == Some(m) {
+            return KILLER_SCORE - 1;
+        }
+        let previous = self.previous_move(ply);
+        if let Some((previous_move, _)) = previous
+            && self.countermoves
+                [previous_move.from().0 as usize * 64 + previous_move.to().0 as usize]
+                == Some(m)
+        {
+            return COUNTERMOVE_SCORE;
+        }
+        let continuation = previous.map_or(0, |previous| {
+            self.continuation_history[continuation_index(previous, m, piece)]
+        });
+        self.history[history_index(color, m)] + continuation
+    }
+
+    /// Rewards the quiet move that caused a beta cutoff, and punishes the quiet moves searched
+    /// before it at the same ply
+    pub(super) fn update_quiet(&mut self, ply: u8, depth: u8, color: Color, best_move: Move) {
+        let killers = &mut self.killers[ply as usize];
+        if killers[0] != Some(best_move) {
+            killers[1] = killers[0];
+            killers[0] = Some(best_move);
+        }
+        let previous = self.previous_move(ply);
+        if let Some((previous_move, _)) = previous {
+            self.countermoves
+                [previous_move.from().0 as usize * 64 + previous_move.to().0 as usize] =
+                Some(best_move);
+        }
+
+        let bonus = (depth as i32 * depth as i32).min(MAX_HISTORY_BONUS);
+        let start = ply as usize * MAX_QUIETS;
+        let searched = &self.quiets[start..start + self.quiet_counts[ply as usize]];
+        for &(m, piece) in searched {
+            let bonus = if m == best_move { bonus } else { -bonus };
+            apply_bonus(&mut self.history[history_index(color, m)], bonus);
+            if let Some(previous) = previous {
+                let index = continuation_index(previous, m, piece);
+                apply_bonus(&mut self.continuation_history[index], bonus);
+            }
+        }
+    }
+}
+
+impl<E: Evaluator> SearchContext<'_, E> {
+    /// Scores the moves of the current ply of the move list
+    pub(super) fn score_moves(&mut self, ply: u8, pv_move: Option<Move>, hash_move: Option<Move>) {
+        let ply_number = self.move_list.ply_number();
+        let color = self.make_unmaker.state.flags.active_color();
+        for i in 0..self.move_list.ply_size(ply_number) {
+            let m = self.move_list.r#move(ply_number, i);
+            let score = if Some(m) == pv_move {
+                PV_MOVE_SCORE
+            } else if Some(m) == hash_move {
+                HASH_MOVE_SCORE
+            } else if m.code().is_capture() || m.code() == MoveCode::QueenPromotion {
+                let (active, passive) = self.make_unmaker.state.split_boards();
+                let attacker = active.piece_at(m.from()).unwrap();
+                let victim = passive.piece_at(m.to()).unwrap_or(PieceType::Pawn);
+                if self.static_exchange_evaluation(m) >= 0 {
+                    GOOD_CAPTURE_SCORE + mvv_lva(victim, attacker)
+                } else {
+                    BAD_CAPTURE_SCORE + mvv_lva(victim, attacker)
+                }
+            } else if m.code().promotion().is_some() {
+                // Under promotions are almost never better than a queen promotion
+                BAD_CAPTURE_SCORE
+            } else {
+                let (active, _) = self.make_unmaker.state.split_boards();
+                let piece = active.piece_at(m.from()).unwrap();
+                self.ordering.quiet_score(ply, color, m, piece)
+            };
+            self.move_list.set_score(i, score);
+        }
+    }
+
+    /// Material won by the side to move after the capture `m`, if both sides then keep
+    /// recapturing on the same square with their least valuable piece while it is profitable
+    pub(super) fn static_exchange_evaluation(&self, m: Move) -> i32 {
+        let state = &*self.make_unmaker.state;
+        let (active, passive) = state.split_boards();
+        let to = m.to();
+        let mut occupation = active.union() | passive.union();
+        let mut gain = [0; 32];
+
+        // The victim of an en passant capture is not on the destination square
+        gain[0] = match passive.piece_at(to) {
+            Some(victim) => SEE_VALUES[victim as usize],
+            None if m.code().is_capture() => SEE_VALUES[PieceType::Pawn as usize],
+            None => 0,
+        };
+        let mut attacker = active.piece_at(m.from()).unwrap();
+        let mut from = m.from();
+        let mut depth = 0;
+        while depth < gain.len() - 1 {
+            occupation.unset(from);
+            let attackers = self.move_generator.attackers_to(state, to, occupation) & occupation;
+            let side = if depth % 2 == 0 { passive } else { active };
+            let Some((square, piece)) = least_valuable_piece(side, attackers) else {
+                break;
+            };
+            depth += 1;
+            // Balance of the side capturing the last attacker
+            gain[depth] = SEE_VALUES[attacker as usize] - gain[depth - 1];
+            from = square;
+            attacker = piece;
+        }
+        // Each side can stop capturing if that is better
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+}
+
+fn least_valuable_piece(side: &ChessBoardSide, attackers: BitBoard) -> Option<(Square, PieceType)> {
+    side.as_array().into_iter().find_map(|(board, piece)| {
+        (*board & attackers)
+            .get_first_square()
+            .map(|square| (square, piece))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chess_core::state::game_state::GameState;
+
+    use super::super::{search::tests::TestPosition, transposition_table::TranspositionTable};
+    use super::*;
+
+    #[test]
+    fn test_static_exchange_evaluation() {
+        for (fen, m, result) in [
+            // Undefended pawn
+            (
+                "4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1",
+                Move::new(Square(3), Square(35), MoveCode::Capture),
+                100,
+            ),
+            // Pawn defended by a pawn
+            (
+                "4k3/8/2p5/3p4/8/8/8/3RK3 w - - 0 1",
+                Move::new(Square(3), Square(35), MoveCode::Capture),
+                -400,
+            ),
+            // Knight defended by a pawn, taken by a pawn
+            (
+                "4k3/8/2p5/3n4/4P3/8/8/4K3 w - - 0 1",
+                Move::new(Square(28), Square(35), MoveCode::Capture),
+                200,
+            ),
+            // Queen behind the rook wins the pawn defended by a rook
+            (
+                "3rk3/8/8/3p4/8/8/3R4/3QK3 w - - 0 1",
+                Move::new(Square(11), Square(35), MoveCode::Capture),
+                100,
+            ),
+            // But the queen taking first loses it
+            (
+                "3rk3/8/8/3p4/8/8/3Q4/3RK3 w - - 0 1",
+                Move::new(Square(11), Square(35), MoveCode::Capture),
+                -300,
+            ),
+        ] {
+            let state = &mut GameState::from_fen(fen.to_string());
+            let transpos = &TranspositionTable::new(1);
+            let search_ctx = SearchContext::new(state, transpos, None);
+            assert_eq!(
+                search_ctx.static_exchange_evaluation(m),
+                result,
+                "FEN: {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_pv_and_hash_moves() {
+        // Both come before the capture of the queen, the move of the principal variation first
+        let position = &mut TestPosition::new("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1");
+        let mut search_ctx = position.search_ctx();
+        let pv_move = Move::new(Square(4), Square(12), MoveCode::QuietMove);
+        let hash_move = Move::new(Square(3), Square(2), MoveCode::QuietMove);
+        let capture = Move::new(Square(3), Square(35), MoveCode::Capture);
+        search_ctx.move_list.new_ply();
+        search_ctx
+            .move_generator
+            .get_pseudo_legal_moves(search_ctx.make_unmaker.state, &mut search_ctx.move_list);
+        search_ctx.score_moves(0, Some(pv_move), Some(hash_move));
+        let ply_number = search_ctx.move_list.ply_number();
+        let first_moves: Vec<_> = (0..3)
+            .map(|i| search_ctx.move_list.pick_move(ply_number, i))
+            .collect();
+        assert_eq!(first_moves, [pv_move, hash_move, capture]);
+    }
+
+    #[test]
+    fn test_history_gravity() {
+        let entry = &mut 0;
+        for _ in 0..1000 {
+            apply_bonus(entry, MAX_HISTORY_BONUS);
+        }
+        assert!(
+            *entry <= MAX_HISTORY && *entry > MAX_HISTORY / 2,
+            "{}",
+            entry
+        );
+    }
+
+    #[test]
+    fn test_killers_and_countermove() {
+        let ordering = &mut MoveOrdering::default();
+        let previous = Move::new(Square(52), Square(36), MoveCode::DoublePawnPush);
+        let killer = Move::new(Square(6), Square(21), MoveCode::QuietMove);
+        let other = Move::new(Square(1), Square(18), MoveCode::QuietMove);
+        ordering.push(0, Some((previous, PieceType::Pawn)));
+
+        ordering.clear_quiets(1);
+        ordering.push_quiet(1, (other, PieceType::Knight));
+        ordering.push_quiet(1, (killer, PieceType::Knight));
+        ordering.update_quiet(1, 4, Color::White, killer);
+        assert_eq!(
+            ordering.quiet_score(1, Color::White, killer, PieceType::Knight),
+            KILLER_SCORE
+        );
+        // Not a killer at another ply, but still the countermove of the same previous move
+        ordering.push(4, Some((previous, PieceType::Pawn)));
+        assert_eq!(
+            ordering.quiet_score(5, Color::White, killer, PieceType::Knight),
+            COUNTERMOVE_SCORE
+        );
+        assert!(ordering.quiet_score(2, Color::White, other, PieceType::Knight) < 0);
+    }
+}
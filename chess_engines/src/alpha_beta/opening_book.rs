@@ -0,0 +1,242 @@
+// Opening book
+// Moves played without searching in known opening positions, so that no time is spent there
+// and games start differently. A book is a list of lines of UCI moves from the starting
+// position. Each position reached by a line is stored by its Zobrist hash with the moves
+// played from it, weighted by the number of lines that play them, so that transpositions
+// share their moves.
+//
+// Polyglot .bin books are not read: their entries are keyed by the Zobrist hash of Polyglot,
+// computed with its own table of 781 random numbers, which is not in the tree yet.
+
+use std::{collections::HashMap, path::Path};
+
+use chess_core::{
+    r#move::{Move, MoveGenerator},
+    state::{game_state::GameState, make_unmake::MakeUnmaker, zobrist_numbers::ZobristNumbers},
+};
+use chrono::Local;
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+/// Plies of the game played from the book at most
+pub const DEFAULT_BOOK_DEPTH: usize = 16;
+const BUILTIN_BOOK: &str = include_str!("opening_book.txt");
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BookSelection {
+    /// Random, in proportion to the weights
+    #[default]
+    Weighted,
+    /// Highest weight, the first one of the book on ties
+    Best,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookMove {
+    pub m: Move,
+    pub weight: u32,
+}
+
+pub struct OpeningBook {
+    positions: HashMap<u64, Vec<BookMove>>,
+    pub selection: BookSelection,
+    /// The book is not used from this ply of the game
+    pub max_depth: usize,
+    zobrist_numbers: ZobristNumbers,
+    /// Xorshift state
+    random: u64,
+}
+
+impl OpeningBook {
+    /// The book compiled into the engine, a few main lines of the usual openings
+    pub fn builtin() -> Self {
+        Self::from_lines(BUILTIN_BOOK).expect("the built-in book is valid")
+    }
+
+    /// Lines of UCI moves, see from_lines
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        if path.extension().is_some_and(|extension| extension == "bin") {
+            return Err(format!(
+                "Polyglot books are not supported yet, {} needs to be lines of UCI moves",
+                path.display()
+            ));
+        }
+        let contents = std::fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+        Self::from_lines(&contents)
+    }
+
+    /// One line of UCI moves from the starting position per line, empty lines and lines
+    /// starting with # are skipped
+    pub fn from_lines(source: &str) -> Result<Self, String> {
+        let move_generator = &MoveGenerator::new();
+        let mut book = OpeningBook {
+            positions: HashMap::new(),
+            selection: BookSelection::default(),
+            max_depth: DEFAULT_BOOK_DEPTH,
+            zobrist_numbers: ZobristNumbers::new(),
+            random: 0x9e37_79b9_7f4a_7c15 ^ Local::now().timestamp_subsec_nanos() as u64,
+        };
+        let lines = source
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+        for (line_index, line) in lines {
+            let state = &mut GameState::from_fen(START_FEN.to_string());
+            let make_unmaker = &mut MakeUnmaker::new(state);
+            for token in line.split_whitespace() {
+                let m = legal_move(move_generator, make_unmaker, token)
+                    .ok_or_else(|| format!("Line {}: illegal move {}", line_index + 1, token))?;
+                let moves = book.positions.entry(make_unmaker.zobrist_hash).or_default();
+                match moves.iter_mut().find(|book_move| book_move.m == m) {
+                    Some(book_move) => book_move.weight += 1,
+                    None => moves.push(BookMove { m, weight: 1 }),
+                }
+                make_unmaker.make_move(m);
+            }
+        }
+        Ok(book)
+    }
+
+    /// Number of positions with book moves
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Moves of the book in a position, in the order of the book
+    pub fn moves(&self, state: &GameState) -> &[BookMove] {
+        self.positions
+            .get(&state.hash(&self.zobrist_numbers))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Book move to play at `ply` of the game, if the position is in the book
+    pub fn probe(&mut self, state: &GameState, ply: usize) -> Option<Move> {
+        if ply >= self.max_depth {
+            return None;
+        }
+        let moves = self.moves(state);
+        let book_move = match self.selection {
+            BookSelection::Best => moves.iter().rev().max_by_key(|book_move| book_move.weight),
+            BookSelection::Weighted => {
+                let total = moves.iter().map(|book_move| book_move.weight as u64).sum();
+                let mut pick = self.below(total);
+                self.moves(state).iter().find(|book_move| {
+                    let found = pick < book_move.weight as u64;
+                    pick = pick.saturating_sub(book_move.weight as u64);
+                    found
+                })
+            }
+        };
+        book_move.map(|book_move| book_move.m)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random % n.max(1)
+    }
+}
+
+/// Plies played before the position, from the fullmove number and the side to move of the FEN
+pub fn game_ply(fen: &str) -> usize {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let fullmove = fields
+        .get(5)
+        .and_then(|fullmove| fullmove.parse::<usize>().ok())
+        .unwrap_or(1);
+    2 * fullmove.saturating_sub(1) + (fields.get(1) == Some(&"b")) as usize
+}
+
+fn legal_move(
+    move_generator: &MoveGenerator,
+    make_unmaker: &mut MakeUnmaker,
+    string: &str,
+) -> Option<Move> {
+    let mut moves = Vec::new();
+    move_generator.get_pseudo_legal_moves(make_unmaker.state, &mut moves);
+    moves.into_iter().find(|&m| {
+        if m.to_string().to_lowercase() != string {
+            return false;
+        }
+        make_unmaker.make_move(m);
+        let legal = move_generator.was_move_legal(make_unmaker.state);
+        make_unmaker.unmake_move(m);
+        legal
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uci_moves(book_moves: &[BookMove]) -> Vec<String> {
+        book_moves
+            .iter()
+            .map(|book_move| book_move.m.to_string().to_lowercase())
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_book() {
+        let mut book = OpeningBook::builtin();
+        assert!(book.len() > 100);
+        let start = &GameState::from_fen(START_FEN.to_string());
+        assert_eq!(
+            uci_moves(book.moves(start)),
+            ["e2e4", "d2d4", "c2c4", "g1f3"]
+        );
+
+        book.selection = BookSelection::Best;
+        let best = book.probe(start, 0).unwrap();
+        assert_eq!(best.to_string(), "e2e4");
+
+        // Every move of the position is played sometimes
+        book.selection = BookSelection::Weighted;
+        let mut played: Vec<String> = (0..200)
+            .map(|_| book.probe(start, 0).unwrap().to_string())
+            .collect();
+        played.sort();
+        played.dedup();
+        assert_eq!(played, ["c2c4", "d2d4", "e2e4", "g1f3"]);
+
+        assert_eq!(book.probe(start, DEFAULT_BOOK_DEPTH), None);
+        let out_of_book = &GameState::from_fen(
+            "rnbqkbnr/pppppppp/8/8/8/7P/PPPPPPP1/RNBQKBNR b KQkq - 0 1".to_string(),
+        );
+        assert_eq!(book.probe(out_of_book, 1), None);
+    }
+
+    #[test]
+    fn test_game_ply() {
+        assert_eq!(game_ply(START_FEN), 0);
+        assert_eq!(game_ply("8/8/8/8/8/8/8/K1k5 b - - 0 12"), 23);
+        assert_eq!(game_ply("8/8/8/8/8/8/8/K1k5 w - -"), 0);
+    }
+
+    #[test]
+    fn test_from_lines() {
+        // Both lines reach the same position before b1c3
+        let book = OpeningBook::from_lines(
+            "# Two move orders\nd2d4 g8f6 c2c4 e7e6 b1c3\n\nc2c4 e7e6 d2d4 g8f6 b1c3 f8b4\n",
+        )
+        .unwrap();
+        let state = &GameState::from_fen(
+            "rnbqkb1r/pppp1ppp/4pn2/8/2PP4/8/PP2PPPP/RNBQKBNR w KQkq - 0 3".to_string(),
+        );
+        assert_eq!(uci_moves(book.moves(state)), ["b1c3"]);
+        assert_eq!(book.moves(state)[0].weight, 2);
+        assert_eq!(book.len(), 9);
+
+        assert_eq!(
+            OpeningBook::from_lines("e2e4 e7e5\ne2e4 e7e4").err(),
+            Some("Line 2: illegal move e7e4".to_string())
+        );
+        assert!(OpeningBook::load("book.bin").is_err());
+    }
+}
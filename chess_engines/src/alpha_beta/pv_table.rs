@@ -0,0 +1,78 @@
+// Triangular principal variation table
+// The principal variation of a node is its best move followed by the principal variation of
+// the child it leads to, which the child left in the next row of the table. Row `ply` holds
+// the variation of the current node at that ply, root first, in at most MAX_PLY - ply moves.
+// The table is allocated with the search context, collecting variations does not allocate.
+
+use chess_core::{
+    r#move::{MAX_PLY, Move, MoveCode},
+    square::Square,
+};
+
+pub(super) struct PvTable {
+    /// Length of the variation of each row, the last one stays empty
+    lengths: [usize; MAX_PLY + 1],
+    /// MAX_PLY rows of MAX_PLY moves
+    moves: Vec<Move>,
+}
+
+impl Default for PvTable {
+    fn default() -> Self {
+        PvTable {
+            lengths: [0; MAX_PLY + 1],
+            moves: vec![Move::new(Square(0), Square(0), MoveCode::QuietMove); MAX_PLY * MAX_PLY],
+        }
+    }
+}
+
+impl PvTable {
+    /// Empties the variation of a node, when it is entered or has no best move
+    pub(super) fn clear(&mut self, ply: u8) {
+        self.lengths[ply as usize] = 0;
+    }
+
+    /// The variation of the node at `ply` becomes `m` followed by the variation of its child
+    pub(super) fn update(&mut self, ply: u8, m: Move) {
+        let ply = ply as usize;
+        let child_length = self.lengths[ply + 1];
+        let (row, child_row) = self.moves.split_at_mut((ply + 1) * MAX_PLY);
+        let row = &mut row[ply * MAX_PLY..];
+        row[0] = m;
+        row[1..=child_length].copy_from_slice(&child_row[..child_length]);
+        self.lengths[ply] = child_length + 1;
+    }
+
+    /// Variation of the node at `ply`, root first
+    pub(super) fn line(&self, ply: u8) -> &[Move] {
+        let ply = ply as usize;
+        &self.moves[ply * MAX_PLY..ply * MAX_PLY + self.lengths[ply]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pv_table() {
+        let m = |from, to| Move::new(Square(from), Square(to), MoveCode::QuietMove);
+        let mut table = PvTable::default();
+        table.clear(2);
+        table.update(2, m(1, 2));
+        table.update(1, m(3, 4));
+        table.update(0, m(5, 6));
+        assert_eq!(table.line(0), [m(5, 6), m(3, 4), m(1, 2)]);
+        assert_eq!(table.line(1), [m(3, 4), m(1, 2)]);
+
+        // A new child replaces the variation of the previous one
+        table.clear(1);
+        table.update(0, m(7, 8));
+        assert_eq!(table.line(0), [m(7, 8)]);
+
+        // The deepest row
+        let last = (MAX_PLY - 1) as u8;
+        table.clear(last);
+        table.update(last, m(9, 10));
+        assert_eq!(table.line(last), [m(9, 10)]);
+    }
+}
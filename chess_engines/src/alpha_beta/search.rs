@@ -1,196 +1,813 @@
-use chrono::{Duration, Local};
+use std::sync::atomic::AtomicBool;
+
+use chrono::{DateTime, Duration, Local};
 
 use chess_core::{
-    r#move::{Move, MoveGenerator, MoveList},
-    state::{game_state::GameState, make_unmake::MakeUnmaker},
+    r#move::{MAX_PLY, Move, MoveGenerator, MoveList},
+    state::{chess_board::PieceType, game_state::GameState, make_unmake::MakeUnmaker},
+};
+
+use super::{
+    evaluation::{Evaluator, SimpleEvaluator},
+    move_ordering::{MoveOrdering, SEE_VALUES},
+    pv_table::PvTable,
+    search_limits::{MAX_DEPTH, SearchLimits},
+    search_params::{
+        FUTILITY_MAX_DEPTH, LATE_MOVE_PRUNING_MAX_DEPTH, LATE_MOVE_REDUCTION_MIN_DEPTH,
+        LATE_MOVE_REDUCTION_MIN_MOVES, REVERSE_FUTILITY_MAX_DEPTH, SearchParams,
+    },
+    search_stats::{Counter, SearchStats},
+    transposition_table::{Bound, TranspositionTable, TtEntry},
 };
 
-use super::transposition_table::{TranspositionTable, TtEntry};
+const MIN_SCORE: i32 = i32::MIN + 1;
+const MAX_SCORE: i32 = i32::MAX;
+
+/// Score of being checkmated at the root, a mate found deeper is scored closer to 0 so that
+/// the shortest mate is preferred
+pub const MATE_SCORE: i32 = 100_000;
+/// Scores beyond this are mates, the search never goes deeper than MAX_PLY plies
+const MATE_BOUND: i32 = MATE_SCORE - MAX_PLY as i32;
+
+/// Half width of the first aspiration window, in centipawns
+const ASPIRATION_WINDOW: i32 = 50;
+/// Past this half width, the aspiration window is opened completely
+const MAX_ASPIRATION_WINDOW: i32 = 1000;
+
+/// The clock is read every this many nodes, reading it at every node would slow the search
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// Number of moves before mate if the score is a mate score, negative if the side to move is
+/// getting mated
+pub fn mate_distance(score: i32) -> Option<i32> {
+    if score >= MATE_BOUND {
+        Some((MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_BOUND {
+        Some(-(MATE_SCORE + score) / 2)
+    } else {
+        None
+    }
+}
+
+/// Mate scores are stored in the transposition table relative to the node instead of the root
+fn score_to_tt(score: i32, ply: u8) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: u8) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+/// Reported after every iteration of the main thread, and after every failed aspiration search
+pub struct SearchInfo {
+    pub depth: u8,
+    pub score: i32,
+    /// Lower if the search failed high, Upper if it failed low
+    pub bound: Bound,
+    /// Time since the start of the search
+    pub time: Duration,
+    pub hashfull: usize,
+    /// Nodes searched by the main thread since the start of the search
+    pub nodes: u64,
+    /// Line of the MultiPV search being reported, from 1
+    pub multi_pv: usize,
+    /// Principal variation from the root, may be empty after a fail low
+    pub pv: Vec<Move>,
+}
+
+/// One of the best lines found by a MultiPV search
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnalysisLine {
+    pub depth: u8,
+    pub score: i32,
+    /// Principal variation from the root
+    pub pv: Vec<Move>,
+}
+
+/// Score and principal variation of the best line
+fn best_line(mut lines: Vec<AnalysisLine>) -> (i32, Vec<Move>) {
+    let line = lines.swap_remove(0);
+    (line.score, line.pv)
+}
+
+pub type InfoCallback<'a> = Box<dyn FnMut(&SearchInfo) + 'a>;
 
-pub struct SearchContext<'a> {
+pub struct SearchContext<'a, E: Evaluator = SimpleEvaluator> {
     pub make_unmaker: MakeUnmaker<'a>,
+    pub evaluator: E,
     pub move_generator: MoveGenerator,
     pub move_list: MoveList,
-    pub transpos: TranspositionTable,
+    pub transpos: &'a TranspositionTable,
     pub max_depth: u8,
+    pub params: SearchParams,
+    /// Nodes searched since the start of the search
+    pub nodes: u64,
+    /// Statistics of the main thread since the start of the search, see search_stats
+    pub stats: SearchStats,
+    /// Only these moves are searched at the root when not empty
+    pub(super) root_moves: Vec<Move>,
+    /// Number of best lines searched at each depth
+    pub multi_pv: usize,
+    /// First moves of the lines already found at the current depth, not searched at the root
+    excluded_moves: Vec<Move>,
+    pub(super) ordering: MoveOrdering,
+    pv_table: PvTable,
+    /// Principal variation of the previous search, root first, tried first along its line
+    prev_pv: Vec<Move>,
+    /// Set while searching the reply to a null move, two null moves in a row prove nothing
+    after_null_move: bool,
+    /// Number of threads used by iterative deepening, see lazy_smp
+    pub threads: usize,
+    /// Set by another thread to stop the search: by the main thread for the helper threads,
+    /// by the caller for the main thread (on the UCI stop command for instance)
+    pub stop: Option<&'a AtomicBool>,
+    /// Limits of the main thread checked during the search, see poll_limits
+    node_limit: Option<u64>,
+    deadline: Option<DateTime<Local>>,
+    /// Set once the node limit or the deadline is reached, the search then stops as if the
+    /// stop flag was set
+    pub(super) interrupted: bool,
+    /// Called with the progress of the search
+    pub on_info: Option<InfoCallback<'a>>,
 }
 
-impl SearchContext<'_> {
-    const MIN_SCORE: i32 = i32::MIN + 1;
-    const MAX_SCORE: i32 = i32::MAX;
+impl<'a> SearchContext<'a> {
+    /// The transposition table is borrowed so that it can be kept between the moves of a game
+    pub fn new(
+        state: &'a mut GameState,
+        transpos: &'a TranspositionTable,
+        max_depth: Option<u8>,
+    ) -> SearchContext<'a> {
+        Self::with_evaluator(state, transpos, max_depth, SimpleEvaluator::default())
+    }
+}
 
-    pub fn new(state: &mut GameState, max_depth: Option<u8>) -> SearchContext<'_> {
+impl<'a, E: Evaluator> SearchContext<'a, E> {
+    pub fn with_evaluator(
+        state: &'a mut GameState,
+        transpos: &'a TranspositionTable,
+        max_depth: Option<u8>,
+        evaluator: E,
+    ) -> SearchContext<'a, E> {
         SearchContext {
             make_unmaker: MakeUnmaker::new(state),
+            evaluator,
             move_generator: MoveGenerator::new(),
             move_list: MoveList::new(),
-            transpos: TranspositionTable::new(),
-            max_depth: max_depth.unwrap_or(1),
+            transpos,
+            max_depth: max_depth.unwrap_or(1).min(MAX_DEPTH),
+            params: SearchParams::default(),
+            nodes: 0,
+            stats: SearchStats::default(),
+            root_moves: Vec::new(),
+            multi_pv: 1,
+            excluded_moves: Vec::new(),
+            ordering: MoveOrdering::default(),
+            pv_table: PvTable::default(),
+            prev_pv: Vec::new(),
+            after_null_move: false,
+            threads: 1,
+            stop: None,
+            node_limit: None,
+            deadline: None,
+            interrupted: false,
+            on_info: None,
         }
     }
 
+    /// Deepens the search until `max_time` has passed, the last iteration is interrupted
     pub fn iterative_deepen(&mut self, max_time: Duration) -> (i32, Vec<Move>) {
-        let mut time_taken = Duration::new(0, 0).unwrap();
-        let prev_depth = self.max_depth;
+        best_line(self.analyse(&SearchLimits::move_time(max_time)))
+    }
+
+    /// Searches every depth from the current max depth to `depth`
+    pub fn iterative_deepen_to_depth(&mut self, depth: u8) -> (i32, Vec<Move>) {
+        best_line(self.analyse(&SearchLimits::depth(depth)))
+    }
 
-        let (mut score, pv) = (0, &mut Vec::new());
+    /// Deepens the search until the main thread has searched `nodes` nodes, the last iteration
+    /// is interrupted
+    pub fn iterative_deepen_to_nodes(&mut self, nodes: u64) -> (i32, Vec<Move>) {
+        best_line(self.analyse(&SearchLimits::nodes(nodes)))
+    }
+
+    /// Deepens the search until one of the limits is reached, and returns the `multi_pv` best
+    /// lines, best first
+    pub fn analyse(&mut self, limits: &SearchLimits) -> Vec<AnalysisLine> {
+        self.lazy_smp(limits)
+    }
 
-        while time_taken < max_time {
-            let start_time = Local::now();
-            let prev_pv = pv.clone();
-            (score, *pv) = self.search(prev_pv);
-            time_taken = Local::now() - start_time;
+    /// Iterative deepening on the calling thread, until one of the limits is reached or the
+    /// search is stopped. Returns the lines of the last complete iteration.
+    pub(super) fn deepen(&mut self, limits: &SearchLimits) -> Vec<AnalysisLine> {
+        let prev_depth = self.max_depth;
+        let search_start = Local::now();
+        // The first iteration is never interrupted, so that there is always a line
+        let stop = self.stop.take();
+        let mut lines = Vec::new();
+
+        loop {
+            let iteration_lines = self.search_lines(&lines, search_start);
+            if self.is_stopped() {
+                break;
+            }
+            lines = iteration_lines;
+            self.stats.end_iteration(self.max_depth, self.nodes);
+            let elapsed = Local::now() - search_start;
+            if limits.is_reached(self.max_depth, elapsed, self.nodes, lines[0].score) {
+                break;
+            }
+            self.stop = stop;
+            self.node_limit = limits.nodes;
+            self.deadline = limits.move_time.map(|time| search_start + time);
             self.max_depth += 1;
         }
         self.max_depth = prev_depth;
-        (score, pv.clone())
+        self.stop = stop;
+        self.node_limit = None;
+        self.deadline = None;
+        self.interrupted = false;
+        lines
+    }
+
+    /// Searches the root `multi_pv` times, each search excluding the first moves of the lines
+    /// found before it. There are fewer lines when there are fewer legal moves, but always one
+    /// unless the search is stopped.
+    fn search_lines(
+        &mut self,
+        prev_lines: &[AnalysisLine],
+        search_start: DateTime<Local>,
+    ) -> Vec<AnalysisLine> {
+        let mut lines = Vec::new();
+        for i in 0..self.multi_pv.max(1) {
+            let prev_line = prev_lines.get(i);
+            let (score, pv) = self.aspiration_search(
+                prev_line.map(|line| line.score),
+                prev_line.map_or(&[], |line| &line.pv),
+                search_start,
+            );
+            // The lines of an interrupted iteration are thrown away, see deepen
+            if self.is_stopped() {
+                break;
+            }
+            let first_move = pv.first().copied();
+            if i > 0 && first_move.is_none() {
+                break;
+            }
+            self.report(score, Bound::Exact, &pv, search_start);
+            lines.push(AnalysisLine {
+                depth: self.max_depth,
+                score,
+                pv,
+            });
+            match first_move {
+                Some(m) => self.excluded_moves.push(m),
+                None => break,
+            }
+        }
+        self.excluded_moves.clear();
+        // A later line can score higher when the search is unstable
+        lines.sort_by_key(|line| std::cmp::Reverse(line.score));
+        lines
+    }
+
+    /// Searches with a window centered on the score of the previous iteration, widening the
+    /// side that failed until the score falls inside the window
+    fn aspiration_search(
+        &mut self,
+        prev_score: Option<i32>,
+        prev_pv: &[Move],
+        search_start: DateTime<Local>,
+    ) -> (i32, Vec<Move>) {
+        let Some(prev_score) = prev_score else {
+            return self.search(prev_pv);
+        };
+
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = prev_score.saturating_sub(delta).max(MIN_SCORE);
+        let mut beta = prev_score.saturating_add(delta);
+        loop {
+            let (score, pv) = self.search_window(alpha, beta, prev_pv);
+            if self.is_stopped() {
+                return (score, pv);
+            }
+            delta *= 2;
+            if score <= alpha && alpha > MIN_SCORE {
+                self.report(score, Bound::Upper, &pv, search_start);
+                alpha = if delta > MAX_ASPIRATION_WINDOW {
+                    MIN_SCORE
+                } else {
+                    score.saturating_sub(delta).max(MIN_SCORE)
+                };
+            } else if score >= beta && beta < MAX_SCORE {
+                self.report(score, Bound::Lower, &pv, search_start);
+                beta = if delta > MAX_ASPIRATION_WINDOW {
+                    MAX_SCORE
+                } else {
+                    score.saturating_add(delta)
+                };
+            } else {
+                return (score, pv);
+            }
+        }
+    }
+
+    fn report(&mut self, score: i32, bound: Bound, pv: &[Move], search_start: DateTime<Local>) {
+        if let Some(on_info) = self.on_info.as_mut() {
+            on_info(&SearchInfo {
+                depth: self.max_depth,
+                score,
+                bound,
+                time: Local::now() - search_start,
+                hashfull: self.transpos.hashfull(),
+                nodes: self.nodes,
+                multi_pv: self.excluded_moves.len() + 1,
+                pv: pv.to_vec(),
+            });
+        }
+    }
+
+    /// Searches the root to the max depth, the moves of the principal variation of the
+    /// previous search are tried first. Returns the score and the principal variation.
+    pub fn search(&mut self, prev_pv: &[Move]) -> (i32, Vec<Move>) {
+        self.search_window(MIN_SCORE, MAX_SCORE, prev_pv)
     }
 
-    pub fn search(&mut self, prev_pv: Vec<Move>) -> (i32, Vec<Move>) {
-        let mut prev_pv = prev_pv;
-        let mut pv = Vec::new();
-        let score =
-            self.alpha_beta_search(Self::MIN_SCORE, Self::MAX_SCORE, 0, &mut pv, &mut prev_pv);
-        (score, pv)
+    fn search_window(&mut self, alpha: i32, beta: i32, prev_pv: &[Move]) -> (i32, Vec<Move>) {
+        self.prev_pv.clear();
+        self.prev_pv.extend_from_slice(prev_pv);
+        let score = self.alpha_beta_search(alpha, beta, self.max_depth, 0, true);
+        (score, self.pv_table.line(0).to_vec())
+    }
+
+    /// Move of the previous principal variation at `ply`, if the node is on its line
+    fn pv_move(&self, ply: u8, follow_pv: bool) -> Option<Move> {
+        if follow_pv {
+            self.prev_pv.get(ply as usize).copied()
+        } else {
+            None
+        }
     }
 
     /// Add pseudo legal moves to move list and returns number and size of ply
-    fn add_moves_to_list(&mut self, prev_pv: &mut Vec<Move>) -> (usize, usize) {
+    ///
+    /// The move of the previous principal variation is tried first, then the transposition
+    /// table move, see move_ordering for the others
+    fn add_moves_to_list(
+        &mut self,
+        ply: u8,
+        pv_move: Option<Move>,
+        hash_move: Option<Move>,
+    ) -> (usize, usize) {
         self.move_list.new_ply();
         self.move_generator
             .get_pseudo_legal_moves(self.make_unmaker.state, &mut self.move_list);
-        self.move_list.order_ply(prev_pv.pop());
+        self.score_moves(ply, pv_move, hash_move);
 
         let ply_number = self.move_list.ply_number();
         (ply_number, self.move_list.ply_size(ply_number))
     }
 
+    fn probe_tt(&mut self, hash: u64) -> Option<TtEntry> {
+        self.stats.count(Counter::TtProbes);
+        let entry = self.transpos.get(hash);
+        if entry.is_some() {
+            self.stats.count(Counter::TtHits);
+        }
+        entry
+    }
+
+    pub fn evaluate(&mut self) -> i32 {
+        self.evaluator.evaluate_with_keys(
+            self.make_unmaker.state,
+            self.make_unmaker.pawn_hash,
+            self.make_unmaker.material_key,
+        )
+    }
+
+    /// Mutable due to move list use but does not modify the state
+    pub fn is_checkmate(&mut self) -> bool {
+        if !self.move_generator.is_check(self.make_unmaker.state) {
+            return false;
+        }
+        self.move_list.new_ply();
+        let mut move_found = false;
+        self.move_generator
+            .get_pseudo_legal_moves(self.make_unmaker.state, &mut self.move_list);
+        // Only legality is checked, the evaluator does not need to follow these moves
+        for m in self.move_list.current_ply() {
+            self.make_unmaker.make_move(*m);
+            if self.move_generator.was_move_legal(self.make_unmaker.state) {
+                move_found = true;
+                self.make_unmaker.unmake_move(*m);
+                break;
+            }
+            self.make_unmaker.unmake_move(*m);
+        }
+        self.move_list.drop_current_ply();
+        !move_found
+    }
+
+    fn make_move(&mut self, m: Move) {
+        self.evaluator.make_move(self.make_unmaker.state, m);
+        self.make_unmaker.make_move(m);
+    }
+
+    fn unmake_move(&mut self, m: Move) {
+        self.make_unmaker.unmake_move(m);
+        self.evaluator.unmake_move(self.make_unmaker.state, m);
+    }
+
+    /// Interrupts the search once the node limit or the deadline is reached
+    fn poll_limits(&mut self) {
+        if self.node_limit.is_some_and(|limit| self.nodes >= limit)
+            || (self.nodes.is_multiple_of(TIME_CHECK_INTERVAL)
+                && self
+                    .deadline
+                    .is_some_and(|deadline| Local::now() >= deadline))
+        {
+            self.interrupted = true;
+        }
+    }
+
+    /// Score of a draw for the side to move, contempt makes the side to move at the root avoid
+    /// draws
+    fn draw_score(&self, ply: u8) -> i32 {
+        if ply.is_multiple_of(2) {
+            -self.params.contempt
+        } else {
+            self.params.contempt
+        }
+    }
+
+    /// Side to move has pieces other than pawns, without them a null move is often worse than
+    /// any real move (zugzwang) and null move pruning must not be used
+    fn has_non_pawn_material(&self) -> bool {
+        let (active, _) = self.make_unmaker.state.split_boards();
+        !(active.knight | active.bishop | active.rook | active.queen).is_empty()
+    }
+
+    /// `depth` is the remaining depth, `ply` the distance to the root. `follow_pv` is set while
+    /// every move from the root is the move of the previous principal variation at its ply.
     fn alpha_beta_search(
         &mut self,
         alpha: i32,
         beta: i32,
         depth: u8,
-        pv: &mut Vec<Move>,
-        prev_pv: &mut Vec<Move>,
+        ply: u8,
+        follow_pv: bool,
     ) -> i32 {
         let mut alpha = alpha;
-        if depth == self.max_depth {
-            return self.quiesce(alpha, beta, depth, pv, prev_pv);
+        let mut beta = beta;
+        self.pv_table.clear(ply);
+        if self.is_stopped() {
+            return 0;
+        }
+        // Extensions can lead this deep, the move list holds no more plies
+        if ply as usize >= MAX_PLY {
+            return self.evaluate();
+        }
+        if ply > 0 {
+            if self.make_unmaker.is_repetition() {
+                return self.draw_score(ply);
+            }
+            // A mate given by the move reaching the fifty move limit still counts
+            if self.make_unmaker.state.halfmove >= 100 {
+                return if self.is_checkmate() {
+                    -MATE_SCORE + ply as i32
+                } else {
+                    self.draw_score(ply)
+                };
+            }
+            // Mate distance pruning: no line from here can beat a shorter mate found before
+            alpha = alpha.max(-MATE_SCORE + ply as i32);
+            beta = beta.min(MATE_SCORE - ply as i32 - 1);
+            if alpha >= beta {
+                self.stats.count(Counter::MateDistancePrunes);
+                return alpha;
+            }
         }
+        if depth == 0 {
+            return self.quiesce(alpha, beta, ply, self.params.quiescence_checks, follow_pv);
+        }
+        self.nodes += 1;
+        self.stats.count(Counter::Nodes);
+        self.poll_limits();
+        let after_null_move = std::mem::take(&mut self.after_null_move);
+
+        let hash = self.make_unmaker.zobrist_hash;
+        let tt_entry = self.probe_tt(hash);
+        // The root is always searched so that a best move is found
+        if ply > 0
+            && let Some(entry) = tt_entry
+            && entry.depth() >= depth
+        {
+            let score = score_from_tt(entry.score(), ply);
+            let cutoff = match entry.bound() {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if cutoff {
+                self.stats.count(Counter::TtCutoffs);
+                return score;
+            }
+        }
+
+        // Pruning is only done in zero window searches, where the exact score is not needed
+        let pv_node = beta > alpha.saturating_add(1);
+        let in_check = self.move_generator.is_check(self.make_unmaker.state);
+        let static_eval = if pv_node || in_check {
+            None
+        } else {
+            Some(self.evaluate())
+        };
 
-        let (ply_number, ply_size) = self.add_moves_to_list(prev_pv);
+        // The static evaluation says nothing about mates
+        let static_eval =
+            static_eval.filter(|_| alpha.abs() < MATE_BOUND && beta.abs() < MATE_BOUND);
+        if let Some(eval) = static_eval {
+            if self.params.reverse_futility_pruning
+                && depth <= REVERSE_FUTILITY_MAX_DEPTH
+                && eval - self.params.reverse_futility_margin * depth as i32 >= beta
+            {
+                self.stats.count(Counter::ReverseFutilityPrunes);
+                return eval;
+            }
+
+            if self.params.null_move_pruning
+                && !after_null_move
+                && eval >= beta
+                && self.has_non_pawn_material()
+            {
+                let reduction = self.params.null_move_reduction + depth / 4;
+                self.make_unmaker.make_null_move();
+                self.ordering.push(ply, None);
+                self.after_null_move = true;
+                let score = -self.alpha_beta_search(
+                    -beta,
+                    -beta + 1,
+                    depth.saturating_sub(1 + reduction),
+                    ply + 1,
+                    false,
+                );
+                self.after_null_move = false;
+                self.make_unmaker.unmake_null_move();
+                if self.is_stopped() {
+                    return 0;
+                }
+                if score >= beta {
+                    self.stats.count(Counter::NullMovePrunes);
+                    return beta;
+                }
+            }
+        }
+
+        let futile = static_eval.is_some_and(|eval| {
+            self.params.futility_pruning
+                && depth <= FUTILITY_MAX_DEPTH
+                && eval + self.params.futility_margin * depth as i32 <= alpha
+        });
+        let late_move_pruning =
+            self.params.late_move_pruning && !pv_node && depth <= LATE_MOVE_PRUNING_MAX_DEPTH;
+
+        let original_alpha = alpha;
+        let pv_move = self.pv_move(ply, follow_pv);
+        let (ply_number, ply_size) =
+            self.add_moves_to_list(ply, pv_move, tt_entry.and_then(|e| e.best_move()));
 
         let mut best_score = i32::MIN + 1;
         let mut best_move = None;
-        let mut line: Vec<Move> = Vec::new();
+        let mut searched_moves = 0;
+        self.ordering.clear_quiets(ply);
+        let color = self.make_unmaker.state.flags.active_color();
 
         for i in 0..ply_size {
-            let m = self.move_list.r#move(ply_number, i);
+            let m = self.move_list.pick_move(ply_number, i);
+            if ply == 0
+                && (!self.root_moves.is_empty() && !self.root_moves.contains(&m)
+                    || self.excluded_moves.contains(&m))
+            {
+                continue;
+            }
+            let (active, _) = self.make_unmaker.state.split_boards();
+            let piece = active.piece_at(m.from()).unwrap();
 
-            self.make_unmaker.make_move(m);
+            self.make_move(m);
             if !self.move_generator.was_move_legal(self.make_unmaker.state) {
-                self.make_unmaker.unmake_move(m);
+                self.unmake_move(m);
+                continue;
+            }
+            let gives_check = self.move_generator.is_check(self.make_unmaker.state);
+            // Quiet moves that are unlikely to raise alpha, at least one move is always searched
+            let prunable = searched_moves > 0 && m.code().is_quiet() && !in_check && !gives_check;
+            if prunable
+                && (futile
+                    || late_move_pruning && searched_moves >= SearchParams::late_move_count(depth))
+            {
+                self.stats.count(if futile {
+                    Counter::FutilityPrunes
+                } else {
+                    Counter::LateMovePrunes
+                });
+                self.unmake_move(m);
                 continue;
             }
-            let score = -self.alpha_beta_search(-beta, -alpha, depth + 1, &mut line, prev_pv);
-            // if let Some(tt_entry) = self.transpos.get(self.make_unmaker.zobrist_hash) {
-            //     score = -tt_entry.score;
-            //     line.push(tt_entry.best_move);
-            // }
-            self.make_unmaker.unmake_move(m);
+
+            // Extensions are bounded so that perpetual checks do not extend the search forever
+            let extension = self.params.check_extensions
+                && gives_check
+                && ((ply + depth) as u16) < 2 * self.max_depth as u16;
+            let new_depth = depth - 1 + extension as u8;
+            self.ordering.push(ply, Some((m, piece)));
+            if m.code().is_quiet() {
+                self.ordering.push_quiet(ply, (m, piece));
+            }
+            let follow_pv = pv_move == Some(m);
+            // Principal variation search: the first move is expected to be the best, the
+            // others are only proven worse with a zero window and searched again if they are not
+            let score = if searched_moves == 0 {
+                -self.alpha_beta_search(-beta, -alpha, new_depth, ply + 1, follow_pv)
+            } else {
+                let reduction = if self.params.late_move_reductions
+                    && prunable
+                    && depth >= LATE_MOVE_REDUCTION_MIN_DEPTH
+                    && searched_moves >= LATE_MOVE_REDUCTION_MIN_MOVES
+                {
+                    self.stats.count(Counter::LateMoveReductions);
+                    SearchParams::late_move_reduction(depth, searched_moves).min(new_depth - 1)
+                } else {
+                    0
+                };
+                let mut score = -self.alpha_beta_search(
+                    -alpha - 1,
+                    -alpha,
+                    new_depth - reduction,
+                    ply + 1,
+                    follow_pv,
+                );
+                if reduction > 0 && score > alpha {
+                    self.stats.count(Counter::LateMoveResearches);
+                    score =
+                        -self.alpha_beta_search(-alpha - 1, -alpha, new_depth, ply + 1, follow_pv);
+                }
+                if score > alpha && score < beta {
+                    -self.alpha_beta_search(-beta, -alpha, new_depth, ply + 1, follow_pv)
+                } else {
+                    score
+                }
+            };
+            searched_moves += 1;
+            self.unmake_move(m);
+            // The result of an interrupted search must not reach the transposition table
+            if self.is_stopped() {
+                self.move_list.drop_current_ply();
+                return 0;
+            }
 
             if score > best_score {
                 best_score = score;
                 best_move = Some(m);
                 if score > alpha {
                     alpha = score;
-                    pv.clear();
-                    pv.append(&mut line.clone());
-                    pv.push(m);
+                    self.pv_table.update(ply, m);
                 }
             }
             if score >= beta {
+                self.stats.count(Counter::BetaCutoffs);
+                if searched_moves == 1 {
+                    self.stats.count(Counter::FirstMoveCutoffs);
+                }
+                if m.code().is_quiet() {
+                    self.ordering.update_quiet(ply, depth, color, m);
+                }
                 break;
             }
         }
 
         self.move_list.drop_current_ply();
 
-        if let Some(best_move) = best_move {
-            self.transpos.store(TtEntry {
-                hash: self.make_unmaker.zobrist_hash,
-                depth,
-                score: best_score,
-                best_move,
-            });
+        if best_move.is_some() {
+            let bound = if best_score >= beta {
+                Bound::Lower
+            } else if best_score > original_alpha {
+                Bound::Exact
+            } else {
+                Bound::Upper
+            };
+            // Without its excluded moves, the root does not have the score of the position
+            if ply > 0 || self.excluded_moves.is_empty() {
+                self.transpos
+                    .store(hash, depth, score_to_tt(best_score, ply), bound, best_move);
+            }
+        } else if in_check {
+            best_score = -MATE_SCORE + ply as i32;
         } else {
-            // If best move if still 0, either stalemate or checkmate
-            // Evaluation function will catch this
-            best_score = self.evaluate();
+            // Stalemate
+            best_score = self.draw_score(ply);
         }
 
         best_score
     }
 
-    fn quiesce(
-        &mut self,
-        alpha: i32,
-        beta: i32,
-        depth: u8,
-        pv: &mut Vec<Move>,
-        prev_pv: &mut Vec<Move>,
-    ) -> i32 {
-        if depth >= self.max_depth + 4 {
-            pv.clear();
+    /// Searches captures until the position is quiet, so that the static evaluation is not
+    /// taken in the middle of an exchange. The side to move can stand pat, except in check
+    /// where every evasion is searched. With `checks`, quiet moves giving check are searched
+    /// too, only at the first ply of the quiescence search so that it still ends.
+    fn quiesce(&mut self, alpha: i32, beta: i32, ply: u8, checks: bool, follow_pv: bool) -> i32 {
+        self.pv_table.clear(ply);
+        if self.is_stopped() {
+            return 0;
+        }
+        self.nodes += 1;
+        self.stats.count(Counter::QuiescenceNodes);
+        self.poll_limits();
+        if ply as usize >= MAX_PLY {
             return self.evaluate();
         }
         let mut alpha = alpha;
-        let (ply_number, ply_size) = self.add_moves_to_list(prev_pv);
+        let original_alpha = alpha;
 
-        let static_score = self.evaluate();
-        let mut best_score = static_score;
-        let mut best_move = None;
-
-        if static_score >= beta {
-            pv.clear();
-            self.move_list.drop_current_ply();
-            return static_score;
+        let hash = self.make_unmaker.zobrist_hash;
+        let tt_entry = self.probe_tt(hash);
+        if let Some(entry) = tt_entry {
+            let score = score_from_tt(entry.score(), ply);
+            let cutoff = match entry.bound() {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if cutoff {
+                self.stats.count(Counter::TtCutoffs);
+                return score;
+            }
         }
 
-        if static_score > alpha {
-            alpha = static_score;
-            // Not sure what to do here
-            best_move = None;
+        let in_check = self.move_generator.is_check(self.make_unmaker.state);
+        let static_score = if in_check {
+            None
+        } else {
+            Some(self.evaluate())
+        };
+        // Getting mated if no evasion is found
+        let mut best_score = static_score.unwrap_or(-MATE_SCORE + ply as i32);
+        if let Some(score) = static_score {
+            if score >= beta {
+                self.transpos
+                    .store(hash, 0, score_to_tt(score, ply), Bound::Lower, None);
+                return score;
+            }
+            alpha = alpha.max(score);
         }
 
-        let mut line: Vec<Move> = Vec::new();
+        let pv_move = self.pv_move(ply, follow_pv);
+        let (ply_number, ply_size) =
+            self.add_moves_to_list(ply, pv_move, tt_entry.and_then(|e| e.best_move()));
+        let mut best_move = None;
 
         for i in 0..ply_size {
-            let m = self.move_list.r#move(ply_number, i);
-            if m.code().is_quiet() {
+            let m = self.move_list.pick_move(ply_number, i);
+            let quiet = m.code().is_quiet();
+            if quiet && !in_check && !checks {
                 continue;
             }
-            // println!("{}Exploring {}", "  ".repeat(depth as usize), m.to_pretty_string());
-            self.make_unmaker.make_move(m);
-            if !self.move_generator.was_move_legal(self.make_unmaker.state) {
-                self.make_unmaker.unmake_move(m);
+            if let Some(score) = static_score
+                && !quiet
+                && self.prune_capture(m, score, alpha)
+            {
+                continue;
+            }
+            self.make_move(m);
+            if !self.move_generator.was_move_legal(self.make_unmaker.state)
+                || quiet && !in_check && !self.move_generator.is_check(self.make_unmaker.state)
+            {
+                self.unmake_move(m);
                 continue;
             }
-            let score = -self.quiesce(-beta, -alpha, depth + 1, &mut line, prev_pv);
-            // println!("{}{} scored {}", "  ".repeat(depth as usize), m.to_pretty_string(), score);
-            // if let Some(tt_entry) = self.transpos.get(self.make_unmaker.zobrist_hash) {
-            //     score = -tt_entry.score;
-            //     line.push(tt_entry.best_move);
-            // }
-            self.make_unmaker.unmake_move(m);
+            let score = -self.quiesce(-beta, -alpha, ply + 1, false, pv_move == Some(m));
+            self.unmake_move(m);
+            if self.is_stopped() {
+                self.move_list.drop_current_ply();
+                return 0;
+            }
             if score > best_score {
                 best_score = score;
                 best_move = Some(m);
                 if score > alpha {
                     alpha = score;
-                    pv.clear();
-                    pv.append(&mut line.clone());
-                    pv.push(m);
+                    self.pv_table.update(ply, m);
                 }
             }
             if score >= beta {
@@ -199,25 +816,43 @@ impl SearchContext<'_> {
         }
         self.move_list.drop_current_ply();
 
-        if let Some(best_move) = best_move {
-            self.transpos.store(TtEntry {
-                hash: self.make_unmaker.zobrist_hash,
-                depth: 0,
-                score: best_score,
-                best_move,
-            });
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
         } else {
-            // No moves found, stop quiescence
-            pv.clear();
-            return static_score;
-        }
-
+            Bound::Upper
+        };
+        self.transpos
+            .store(hash, 0, score_to_tt(best_score, ply), bound, best_move);
         best_score
     }
+
+    /// Skips a capture of the quiescence search that is unlikely to raise alpha: even winning
+    /// the captured piece leaves the score too far below it (delta pruning), or it loses
+    /// material (SEE pruning)
+    fn prune_capture(&mut self, m: Move, static_score: i32, alpha: i32) -> bool {
+        if m.code().promotion().is_some() {
+            return false;
+        }
+        let (_, passive) = self.make_unmaker.state.split_boards();
+        let victim = passive.piece_at(m.to()).unwrap_or(PieceType::Pawn);
+        if self.params.delta_pruning
+            && static_score + SEE_VALUES[victim as usize] + self.params.delta_margin <= alpha
+        {
+            self.stats.count(Counter::DeltaPrunes);
+            true
+        } else if self.params.see_pruning && self.static_exchange_evaluation(m) < 0 {
+            self.stats.count(Counter::SeePrunes);
+            true
+        } else {
+            false
+        }
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(super) mod tests {
     use std::{
         io::{BufRead, BufReader, Write},
         process::{Command, Stdio},
@@ -227,8 +862,52 @@ mod tests {
 
     use chess_core::{color::Color, r#move::MoveCode, square::Square};
 
+    use super::super::evaluation::endgame::KNOWN_WIN;
     use super::*;
 
+    /// A position with its own transposition table, to create search contexts from
+    pub(in super::super) struct TestPosition {
+        pub(in super::super) state: GameState,
+        transpos: TranspositionTable,
+    }
+
+    impl TestPosition {
+        pub(in super::super) fn new(fen: &str) -> Self {
+            TestPosition {
+                state: GameState::from_fen(fen.to_string()),
+                transpos: TranspositionTable::new(1),
+            }
+        }
+
+        pub(in super::super) fn search_ctx(&mut self) -> SearchContext<'_> {
+            SearchContext::new(&mut self.state, &self.transpos, None)
+        }
+
+        pub(in super::super) fn search_ctx_with<E: Evaluator>(
+            &mut self,
+            evaluator: E,
+        ) -> SearchContext<'_, E> {
+            SearchContext::with_evaluator(&mut self.state, &self.transpos, None, evaluator)
+        }
+    }
+
+    #[test]
+    fn test_is_checkmate() {
+        for (fen, result) in [
+            // starting position
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                false,
+            ),
+            // mate
+            ("8/8/8/8/8/8/5KQ1/7k b - - 0 1", true),
+        ] {
+            let position = &mut TestPosition::new(fen);
+            let mut search_context = position.search_ctx();
+            assert_eq!(search_context.is_checkmate(), result);
+        }
+    }
+
     #[test]
     fn test_quiesce() {
         let cases = [
@@ -237,53 +916,46 @@ mod tests {
                 "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
                 (0, 0, vec![]),
             ),
-            // white is up by a pawn, black has 4 more mobility
+            // white is up by a pawn, the h7 pawn was worth 5 more on its square and the black h8
+            // rook gets a semi-open file with 5 safe squares
             (
                 "rnbqkbnr/ppppppp1/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
-                (80, 80, vec![]),
+                (85, 90, vec![]),
             ),
-            // white is up by a knight, black to play
+            // white is up by a knight, black to play, the missing knight was badly placed
             (
                 "rnbqkb1r/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1",
-                (-350, -300, vec![]),
+                (-300, -250, vec![]),
             ),
-            // One capture + lots of extra mobility
+            // One capture leaving a queen against a lone king, a known win
             (
                 "8/8/8/8/8/8/qQ/5k1K w - - 0 1",
                 (
-                    900,
-                    1050,
+                    KNOWN_WIN + 900,
+                    KNOWN_WIN + 1200,
                     vec![Move::new(Square(9), Square(8), MoveCode::Capture)],
                 ),
             ),
-            // Two captures
-            (
-                "8/8/8/8/8/1p6/qR6/5k1K w - - 0 1",
-                (
-                    -150,
-                    -50,
-                    vec![
-                        Move::new(Square(17), Square(8), MoveCode::Capture),
-                        Move::new(Square(9), Square(8), MoveCode::Capture),
-                    ],
-                ),
-            ),
-            // Capture rook with queen but get taken or capture pawn with no capture
+            // Two captures would leave a lost king and pawn ending, the pawn on a2 promotes
+            ("8/8/8/8/8/1p6/qR6/5k1K w - - 0 1", (-600, -450, vec![])),
+            // Capture rook with queen but get taken or capture pawn with no capture, without pawns
+            // the two rooks against the queen are drawish
             (
                 "8/8/8/8/1p6/8/rQ6/r4k1K w - - 0 1",
                 (
-                    -150,
                     -50,
+                    -25,
                     vec![Move::new(Square(9), Square(25), MoveCode::Capture)],
                 ),
             ),
             // Capture + promotion sequence resulting in gain for white
             // Black is not forced to make second capture. Static eval can be considered best move.
+            // The passed pawn on h7 is close to promotion.
             (
                 "k7/pp5r/6P1/3p4/4P3/8/6PP/7K w - - 0 1",
                 (
-                    50,
-                    150,
+                    250,
+                    300,
                     vec![
                         Move::new(Square(46), Square(55), MoveCode::Capture),
                         // Move::new(Square(35), Square(28), MoveCode::Capture),
@@ -293,24 +965,313 @@ mod tests {
             ),
         ];
         for (fen, (lower_bound, upper_bound, expected_pv)) in cases {
-            let state = &mut GameState::from_fen(fen.to_string());
-            let prev_pv = &mut Vec::new();
-            let pv = &mut Vec::new();
-            let mut context = SearchContext::new(state, None);
-            let score = context.quiesce(
-                SearchContext::MIN_SCORE,
-                SearchContext::MAX_SCORE,
-                0,
-                pv,
-                prev_pv,
-            );
-            assert_eq!(*prev_pv, vec![]);
-            assert_eq!(*pv, expected_pv, "State: {:?}", state);
+            let position = &mut TestPosition::new(fen);
+            let mut context = position.search_ctx();
+            let score = context.quiesce(MIN_SCORE, MAX_SCORE, 0, false, false);
+            let pv = context.pv_table.line(0).to_vec();
+            drop(context);
+            assert_eq!(pv, expected_pv, "State: {:?}", position.state);
             assert!(score >= lower_bound, "{} < {}", score, lower_bound);
             assert!(score <= upper_bound, "{} > {}", score, upper_bound);
         }
     }
 
+    #[test]
+    fn test_quiesce_checks() {
+        let quiesce = |fen: &str, checks: bool| {
+            let position = &mut TestPosition::new(fen);
+            let mut context = position.search_ctx();
+            let score = context.quiesce(MIN_SCORE, MAX_SCORE, 0, checks, false);
+            (score, context.pv_table.line(0).to_vec())
+        };
+        // In check the side to move cannot stand pat, and is mated without evasions
+        assert_eq!(
+            quiesce("3R2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", false).0,
+            -MATE_SCORE
+        );
+        // The king takes the checking queen, the only evasion
+        let (score, pv) = quiesce("6k1/5ppp/8/8/8/8/5PPP/5qK1 w - - 0 1", false);
+        assert!(score.abs() < 100, "{}", score);
+        assert_eq!(pv, [Move::new(Square(6), Square(5), MoveCode::Capture)]);
+
+        // The back rank mate is a quiet check
+        let fen = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+        assert!(quiesce(fen, false).0 < MATE_BOUND);
+        let (score, pv) = quiesce(fen, true);
+        assert_eq!(score, MATE_SCORE - 1);
+        assert_eq!(pv, [Move::new(Square(3), Square(59), MoveCode::QuietMove)]);
+    }
+
+    #[test]
+    fn test_selective_search() {
+        // White can take the queen with the bishop
+        let fen = "rnb1kbnr/pppp1ppp/8/4p1q1/4P3/3P4/PPP2PPP/RNBQKBNR w KQkq - 0 1";
+        let search = |params: SearchParams| {
+            let position = &mut TestPosition::new(fen);
+            let mut search_ctx = position.search_ctx();
+            search_ctx.params = params;
+            let (score, pv) = search_ctx.iterative_deepen_to_depth(5);
+            (score, pv, search_ctx.nodes)
+        };
+
+        let (_, full_pv, full_nodes) = search(SearchParams::disabled());
+        let (score, pv, nodes) = search(SearchParams::default());
+        assert!(nodes < full_nodes, "{} >= {}", nodes, full_nodes);
+        assert!(score > 500, "{}", score);
+        assert_eq!(pv.first(), full_pv.first());
+        assert_eq!(
+            pv.first(),
+            Some(&Move::new(Square(2), Square(38), MoveCode::Capture))
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        // The depth is capped, so that the bound of the check extensions cannot overflow
+        let state = &mut GameState::from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1".to_string());
+        let transpos = &TranspositionTable::new(1);
+        let search_ctx = SearchContext::new(state, transpos, Some(200));
+        assert_eq!(search_ctx.max_depth, MAX_DEPTH);
+    }
+
+    #[test]
+    fn test_max_ply() {
+        // Checks are extended and searched by the quiescence search past the end of the move
+        // list, which is filled as if the search was already that deep
+        let position = &mut TestPosition::new("k7/8/8/8/8/8/8/KQ6 w - - 0 1");
+        let mut search_ctx = position.search_ctx();
+        let ply = MAX_PLY - 3;
+        for _ in 0..ply {
+            search_ctx.move_list.new_ply();
+            search_ctx
+                .move_generator
+                .get_pseudo_legal_moves(search_ctx.make_unmaker.state, &mut search_ctx.move_list);
+        }
+        let score = search_ctx.alpha_beta_search(MIN_SCORE, MAX_SCORE, 6, ply as u8, false);
+        assert!(score > 0);
+        assert_eq!(search_ctx.move_list.ply_number(), ply);
+    }
+
+    #[test]
+    fn test_mate_scores() {
+        for (fen, depth, moves) in [
+            // Back rank mate
+            ("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 2, 1),
+            // The king must be brought closer first
+            ("k7/8/2K5/8/8/8/8/7R w - - 0 1", 4, 2),
+            // Black gets mated whatever it plays
+            ("k7/8/1K6/8/8/8/8/6R1 b - - 0 1", 3, -1),
+        ] {
+            let position = &mut TestPosition::new(fen);
+            let mut search_ctx = position.search_ctx();
+            let (score, _) = search_ctx.iterative_deepen_to_depth(depth);
+            assert_eq!(mate_distance(score), Some(moves), "FEN: {}", fen);
+        }
+        assert_eq!(mate_distance(MATE_SCORE - 1), Some(1));
+        assert_eq!(mate_distance(-MATE_SCORE + 2), Some(-1));
+        assert_eq!(mate_distance(900), None);
+    }
+
+    #[test]
+    fn test_draws() {
+        for (fen, contempt, expected_score) in [
+            // Stalemate
+            ("k7/8/1Q6/8/8/8/8/7K b - - 0 1", 0, 0),
+            ("k7/8/1Q6/8/8/8/8/7K b - - 0 1", 20, -20),
+            // Every move reaches the fifty move limit
+            ("k7/8/8/8/8/8/8/KQ6 w - - 99 1", 0, 0),
+            ("k7/8/8/8/8/8/8/KQ6 w - - 99 1", 20, -20),
+            // Mate on the move reaching the limit is not a draw
+            ("k7/8/1K6/8/8/8/8/6Q1 w - - 99 1", 20, MATE_SCORE - 1),
+        ] {
+            let position = &mut TestPosition::new(fen);
+            let mut search_ctx = position.search_ctx();
+            search_ctx.params.contempt = contempt;
+            let (score, _) = search_ctx.iterative_deepen_to_depth(3);
+            assert_eq!(score, expected_score, "FEN: {}", fen);
+        }
+    }
+
+    #[test]
+    fn test_repetition_draw() {
+        // Black to move is a queen down, but can repeat the position played before
+        let position = &mut TestPosition::new("k7/8/8/8/8/8/8/KQ6 w - - 0 1");
+        let mut search_ctx = position.search_ctx();
+        for m in [
+            Move::new(Square(1), Square(9), MoveCode::QuietMove),
+            Move::new(Square(56), Square(57), MoveCode::QuietMove),
+            Move::new(Square(9), Square(1), MoveCode::QuietMove),
+        ] {
+            search_ctx.make_unmaker.make_move(m);
+        }
+        let (score, pv) = search_ctx.iterative_deepen_to_depth(1);
+        assert_eq!(score, 0);
+        assert_eq!(
+            pv.first(),
+            Some(&Move::new(Square(57), Square(56), MoveCode::QuietMove))
+        );
+    }
+
+    #[test]
+    fn test_aspiration_windows() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnb1kbnr/pppp1ppp/8/4p1q1/4P3/3P4/PPP2PPP/RNBQKBNR w KQkq - 0 1",
+        ] {
+            let position = &mut TestPosition::new(fen);
+            let mut full_window_ctx = position.search_ctx();
+            full_window_ctx.max_depth = 4;
+            // Pruning depends on the window, only plain alpha-beta gives the same score
+            full_window_ctx.params = SearchParams::disabled();
+            let (expected_score, _) = full_window_ctx.search(&[]);
+
+            let position = &mut TestPosition::new(fen);
+            let infos = &mut Vec::new();
+            let mut search_ctx = position.search_ctx();
+            search_ctx.params = SearchParams::disabled();
+            search_ctx.on_info = Some(Box::new(|info: &SearchInfo| {
+                infos.push((info.depth, info.score, info.bound))
+            }));
+            let (score, pv) = search_ctx.iterative_deepen_to_depth(4);
+            drop(search_ctx);
+
+            assert_eq!(score, expected_score, "FEN: {}", fen);
+            assert!(!pv.is_empty());
+            // Every depth ends with an exact score, failed searches are reported before it
+            for depth in 1..=4 {
+                let depth_infos: Vec<_> = infos.iter().filter(|i| i.0 == depth).collect();
+                let (last, failed) = depth_infos.split_last().unwrap();
+                assert_eq!(last.2, Bound::Exact);
+                assert!(failed.iter().all(|i| i.2 != Bound::Exact));
+            }
+            assert_eq!(infos.last().unwrap().1, score);
+        }
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let position =
+            &mut TestPosition::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let iterations = &mut Vec::new();
+        let mut search_ctx = position.search_ctx();
+        search_ctx.on_info = Some(Box::new(|info: &SearchInfo| {
+            if info.bound == Bound::Exact {
+                iterations.push((info.nodes, info.pv.clone()))
+            }
+        }));
+        let (_, pv) = search_ctx.iterative_deepen_to_nodes(20_000);
+        let nodes = search_ctx.nodes;
+        drop(search_ctx);
+
+        // The iteration reaching the limit is interrupted, the line comes from the one before
+        assert_eq!(nodes, 20_000);
+        let (last_nodes, last_pv) = iterations.last().unwrap();
+        assert!(*last_nodes < 20_000);
+        assert_eq!(&pv, last_pv);
+    }
+
+    #[test]
+    fn test_stop() {
+        let stop = &AtomicBool::new(true);
+        let position =
+            &mut TestPosition::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mut search_ctx = position.search_ctx();
+        let start = Local::now();
+        let (_, pv) = search_ctx.iterative_deepen(Duration::milliseconds(200));
+        assert!(!pv.is_empty());
+        let elapsed = Local::now() - start;
+        assert!(elapsed < Duration::milliseconds(400), "{}", elapsed);
+
+        // Stopped before it starts, the search still completes its first iteration
+        search_ctx.stop = Some(stop);
+        let lines = search_ctx.analyse(&SearchLimits::default());
+        assert_eq!(lines[0].depth, 1);
+        assert!(!lines[0].pv.is_empty());
+    }
+
+    #[test]
+    fn test_search_limits() {
+        // Rd8 mates, but only the king and the h pawn may move
+        let position = &mut TestPosition::new("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1");
+        let mut search_ctx = position.search_ctx();
+        let search_moves = vec![
+            Move::new(Square(6), Square(5), MoveCode::QuietMove),
+            Move::new(Square(15), Square(23), MoveCode::QuietMove),
+        ];
+        let lines = search_ctx.analyse(&SearchLimits {
+            depth: Some(3),
+            search_moves: search_moves.clone(),
+            ..Default::default()
+        });
+        assert!(search_moves.contains(lines[0].pv.first().unwrap()));
+        assert_eq!(mate_distance(lines[0].score), None);
+
+        // The search stops as soon as the mate is found
+        let position = &mut TestPosition::new("k7/8/2K5/8/8/8/8/7R w - - 0 1");
+        let mut search_ctx = position.search_ctx();
+        let lines = search_ctx.analyse(&SearchLimits {
+            mate: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(mate_distance(lines[0].score), Some(2));
+        assert!(lines[0].depth <= 5, "{}", lines[0].depth);
+
+        // The search stops once the node budget is spent
+        let position =
+            &mut TestPosition::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mut search_ctx = position.search_ctx();
+        let lines = search_ctx.analyse(&SearchLimits::nodes(5_000));
+        assert_eq!(search_ctx.nodes, 5_000);
+        assert!(lines[0].depth < 10, "{}", lines[0].depth);
+        drop(search_ctx);
+
+        // Limits are combined, the first one reached stops the search
+        let mut search_ctx = position.search_ctx();
+        let lines = search_ctx.analyse(&SearchLimits {
+            depth: Some(2),
+            nodes: Some(1_000_000),
+            move_time: Some(Duration::seconds(60)),
+            ..Default::default()
+        });
+        assert_eq!(lines[0].depth, 2);
+    }
+
+    #[test]
+    fn test_multi_pv() {
+        // White can take the queen, the rook or the knight, in that order of value
+        let position = &mut TestPosition::new("6k1/5ppp/r7/2n5/1P5q/P4N2/4BPPP/6K1 w - - 0 1");
+        let reported = &mut Vec::new();
+        let mut search_ctx = position.search_ctx();
+        search_ctx.multi_pv = 3;
+        search_ctx.on_info = Some(Box::new(|info: &SearchInfo| {
+            if info.bound == Bound::Exact {
+                reported.push((info.depth, info.multi_pv))
+            }
+        }));
+        let lines = search_ctx.analyse(&SearchLimits::depth(3));
+        drop(search_ctx);
+
+        let first_moves: Vec<_> = lines
+            .iter()
+            .map(|line| line.pv.first().unwrap().to_string())
+            .collect();
+        assert_eq!(first_moves, ["f3h4", "e2a6", "b4c5"]);
+        assert!(
+            lines.windows(2).all(|w| w[0].score > w[1].score),
+            "{:?}",
+            lines
+        );
+        assert!(lines.iter().all(|line| line.depth == 3));
+        assert_eq!(reported[reported.len() - 3..], [(3, 1), (3, 2), (3, 3)]);
+
+        // Fewer lines than legal moves
+        let position = &mut TestPosition::new("7k/8/8/8/8/8/8/K7 w - - 0 1");
+        let mut search_ctx = position.search_ctx();
+        search_ctx.multi_pv = 5;
+        assert_eq!(search_ctx.analyse(&SearchLimits::depth(2)).len(), 3);
+    }
+
     #[test]
     fn test_vs_stockfish() {
         // 27/01: current estimated elo: 2000
@@ -326,7 +1287,8 @@ mod tests {
         let mut state = GameState::from_fen(
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
         );
-        let mut search_ctx = SearchContext::new(&mut state, None);
+        let transpos = TranspositionTable::default();
+        let mut search_ctx = SearchContext::new(&mut state, &transpos, None);
 
         stockfish_stdin
             .write_all("setoption name UCI_LimitStrength value true\n".as_bytes())
@@ -339,7 +1301,7 @@ mod tests {
         let mut time_sum = TimeDelta::zero();
         let mut time_count = 0;
 
-        while !search_ctx.is_checkmate() && search_ctx.make_unmaker.state.halfmove < 200 {
+        while !search_ctx.is_checkmate() && search_ctx.make_unmaker.hash_history.len() < 200 {
             if search_ctx.make_unmaker.state.flags.active_color() == Color::White {
                 let start_time = Local::now();
                 let (score, pv) =
@@ -351,7 +1313,7 @@ mod tests {
                     println!("{}", m);
                 }
                 dbg!(score);
-                let m = pv.last().unwrap();
+                let m = pv.first().unwrap();
                 dbg!(m);
                 search_ctx.make_unmaker.make_move(*m);
                 println!("{}", search_ctx.make_unmaker.state.to_fen());
@@ -378,13 +1340,12 @@ mod tests {
                     search_ctx.make_unmaker.state,
                     &mut search_ctx.move_list,
                 );
-                let m = search_ctx
+                let m = *search_ctx
                     .move_list
                     .current_ply()
-                    .into_iter()
+                    .iter()
                     .find(|m| m.matches_perft_string(move_str))
-                    .unwrap()
-                    .clone();
+                    .unwrap();
                 search_ctx.move_list.drop_current_ply();
                 search_ctx.make_unmaker.make_move(m);
                 println!("{}", search_ctx.make_unmaker.state.to_fen());
@@ -409,5 +1370,6 @@ mod tests {
         }
 
         stockfish_cli.kill().unwrap();
+        stockfish_cli.wait().unwrap();
     }
 }
@@ -0,0 +1,99 @@
+// Search limits
+// What ends iterative deepening, as in the UCI go command. The depth and the mate are checked
+// between iterations. The nodes and the time are also polled during the search, which is then
+// interrupted and returns the lines of the last complete iteration: the first iteration is
+// never interrupted, so that there is always a line. The search stops as soon as one of the
+// limits is reached, the time can then be combined with the others. With a single thread,
+// searches limited by depth or by nodes are reproducible.
+
+use chess_core::r#move::Move;
+use chrono::Duration;
+
+use super::search::mate_distance;
+
+/// Iterative deepening never goes deeper, the tree stays small in forced mates for instance
+pub const MAX_DEPTH: u8 = 64;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchLimits {
+    /// Last depth searched
+    pub depth: Option<u8>,
+    /// Nodes searched by the main thread
+    pub nodes: Option<u64>,
+    /// Stops once the side to move is proven to mate in at most this many moves
+    pub mate: Option<u32>,
+    /// Time since the start of the search
+    pub move_time: Option<Duration>,
+    /// Only these moves are searched at the root when not empty
+    pub search_moves: Vec<Move>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u8) -> Self {
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        SearchLimits {
+            nodes: Some(nodes),
+            ..Default::default()
+        }
+    }
+
+    pub fn move_time(move_time: Duration) -> Self {
+        SearchLimits {
+            move_time: Some(move_time),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the search stops after an iteration of `depth` ending `elapsed` after the start,
+    /// with `nodes` searched since the start and the best line scoring `score`
+    pub(super) fn is_reached(&self, depth: u8, elapsed: Duration, nodes: u64, score: i32) -> bool {
+        depth >= MAX_DEPTH
+            || self.depth.is_some_and(|max_depth| depth >= max_depth)
+            || self.nodes.is_some_and(|max_nodes| nodes >= max_nodes)
+            || self.move_time.is_some_and(|time| elapsed >= time)
+            || self.mate.is_some_and(|moves| {
+                mate_distance(score).is_some_and(|distance| (1..=moves as i32).contains(&distance))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::search::MATE_SCORE;
+    use super::*;
+
+    #[test]
+    fn test_is_reached() {
+        let second = Duration::seconds(1);
+        assert!(!SearchLimits::default().is_reached(10, second, 1000, 0));
+        assert!(SearchLimits::default().is_reached(MAX_DEPTH, second, 1000, 0));
+
+        let limits = SearchLimits::depth(5);
+        assert!(!limits.is_reached(4, second, 1000, 0));
+        assert!(limits.is_reached(5, second, 1000, 0));
+
+        let limits = SearchLimits {
+            nodes: Some(1000),
+            move_time: Some(second),
+            ..Default::default()
+        };
+        assert!(!limits.is_reached(4, Duration::milliseconds(10), 999, 0));
+        assert!(limits.is_reached(4, Duration::milliseconds(10), 1000, 0));
+        assert!(limits.is_reached(4, second, 10, 0));
+
+        let limits = SearchLimits {
+            mate: Some(2),
+            ..Default::default()
+        };
+        assert!(limits.is_reached(3, second, 10, MATE_SCORE - 3));
+        assert!(!limits.is_reached(5, second, 10, MATE_SCORE - 5));
+        // Getting mated does not stop the search
+        assert!(!limits.is_reached(3, second, 10, -MATE_SCORE + 2));
+    }
+}
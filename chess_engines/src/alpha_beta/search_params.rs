@@ -0,0 +1,134 @@
+// Selective search
+// Alpha-beta only prunes moves that are proven useless. The techniques below also prune or
+// reduce moves that are very likely useless, so that the important lines are searched deeper
+// in the same time. They are all switchable so that what each one brings can be measured.
+
+use std::sync::LazyLock;
+
+/// Reverse futility pruning only applies to nodes this close to the horizon
+pub(super) const REVERSE_FUTILITY_MAX_DEPTH: u8 = 6;
+/// Futility pruning only applies to nodes this close to the horizon
+pub(super) const FUTILITY_MAX_DEPTH: u8 = 2;
+/// Late move pruning only applies to nodes this close to the horizon
+pub(super) const LATE_MOVE_PRUNING_MAX_DEPTH: u8 = 3;
+/// Late move reductions only apply to nodes with at least this much depth left
+pub(super) const LATE_MOVE_REDUCTION_MIN_DEPTH: u8 = 3;
+/// Number of moves searched at full depth before reducing the others
+pub(super) const LATE_MOVE_REDUCTION_MIN_MOVES: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchParams {
+    /// Let the opponent play twice, a position still winning after that is not searched
+    pub null_move_pruning: bool,
+    /// Depth reduction of the null move search, one more ply is taken per 4 plies of depth
+    pub null_move_reduction: u8,
+    /// Quiet moves ordered late are searched less deeply, then again if they raise alpha
+    pub late_move_reductions: bool,
+    /// Quiet moves are skipped near the horizon if the static evaluation is too far below alpha
+    pub futility_pruning: bool,
+    /// Futility margin per ply of remaining depth, in centipawns
+    pub futility_margin: i32,
+    /// Nodes near the horizon fail high if the static evaluation is far enough above beta
+    pub reverse_futility_pruning: bool,
+    /// Reverse futility margin per ply of remaining depth, in centipawns
+    pub reverse_futility_margin: i32,
+    /// Only the first quiet moves are searched near the horizon
+    pub late_move_pruning: bool,
+    /// Moves giving check are searched one ply deeper
+    pub check_extensions: bool,
+    /// Captures are skipped by the quiescence search if winning the captured piece still
+    /// leaves the static evaluation below alpha
+    pub delta_pruning: bool,
+    /// Delta pruning margin, in centipawns
+    pub delta_margin: i32,
+    /// Captures losing material by static exchange evaluation are skipped by the quiescence
+    /// search
+    pub see_pruning: bool,
+    /// The first ply of the quiescence search also searches quiet moves giving check
+    pub quiescence_checks: bool,
+    /// Penalty of a draw for the side to move at the root, in centipawns, a positive contempt
+    /// avoids draws against weaker opponents
+    pub contempt: i32,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            null_move_pruning: true,
+            null_move_reduction: 2,
+            late_move_reductions: true,
+            futility_pruning: true,
+            futility_margin: 150,
+            reverse_futility_pruning: true,
+            reverse_futility_margin: 100,
+            late_move_pruning: true,
+            check_extensions: true,
+            delta_pruning: true,
+            delta_margin: 200,
+            see_pruning: true,
+            quiescence_checks: false,
+            contempt: 0,
+        }
+    }
+}
+
+impl SearchParams {
+    /// Plain alpha-beta, every move is searched to the full depth
+    pub fn disabled() -> Self {
+        SearchParams {
+            null_move_pruning: false,
+            late_move_reductions: false,
+            futility_pruning: false,
+            reverse_futility_pruning: false,
+            late_move_pruning: false,
+            check_extensions: false,
+            delta_pruning: false,
+            see_pruning: false,
+            ..Self::default()
+        }
+    }
+
+    /// Number of quiet moves searched before late move pruning starts
+    pub(super) fn late_move_count(depth: u8) -> usize {
+        3 + (depth as usize) * (depth as usize)
+    }
+
+    /// Late move reduction of the move with index `move_number` at `depth`
+    pub(super) fn late_move_reduction(depth: u8, move_number: usize) -> u8 {
+        REDUCTIONS[(depth as usize).min(63)][move_number.min(63)]
+    }
+}
+
+/// Reductions grow with the logarithm of both the depth and the move number
+static REDUCTIONS: LazyLock<[[u8; 64]; 64]> = LazyLock::new(|| {
+    let mut reductions = [[0; 64]; 64];
+    for (depth, row) in reductions.iter_mut().enumerate().skip(1) {
+        for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+            let r = 0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25;
+            *reduction = r as u8;
+        }
+    }
+    reductions
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_late_move_reduction() {
+        assert_eq!(SearchParams::late_move_reduction(3, 3), 1);
+        // Never decreases with the depth or the move number
+        for depth in 1..64 {
+            for move_number in 1..64 {
+                let r = SearchParams::late_move_reduction(depth, move_number);
+                assert!(r >= SearchParams::late_move_reduction(depth - 1, move_number));
+                assert!(r >= SearchParams::late_move_reduction(depth, move_number - 1));
+            }
+        }
+        assert_eq!(
+            SearchParams::late_move_reduction(200, 200),
+            SearchParams::late_move_reduction(63, 63)
+        );
+    }
+}
@@ -0,0 +1,224 @@
+// Search statistics
+// Counters of what the main search thread does, to tune the search: nodes, transposition table
+// use, cutoffs and how often each pruning rule fired. They are only collected with the stats
+// feature. Without it SearchStats is empty and counting does nothing, so that the counters cost
+// nothing in normal builds.
+
+use std::fmt::{self, Display};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    /// Nodes of the main search
+    Nodes,
+    /// Nodes of the quiescence search
+    QuiescenceNodes,
+    TtProbes,
+    /// Probes that found the position
+    TtHits,
+    /// Hits whose score was used instead of searching the node
+    TtCutoffs,
+    BetaCutoffs,
+    /// Beta cutoffs by the first move searched
+    FirstMoveCutoffs,
+    MateDistancePrunes,
+    NullMovePrunes,
+    ReverseFutilityPrunes,
+    FutilityPrunes,
+    LateMovePrunes,
+    /// Moves searched with a late move reduction
+    LateMoveReductions,
+    /// Reduced moves searched again at full depth
+    LateMoveResearches,
+    DeltaPrunes,
+    SeePrunes,
+}
+
+#[cfg(feature = "stats")]
+const COUNTERS: usize = Counter::SeePrunes as usize + 1;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    #[cfg(feature = "stats")]
+    counters: [u64; COUNTERS],
+    /// Depth and nodes since the start of the search after each iteration
+    #[cfg(feature = "stats")]
+    iterations: Vec<(u8, u64)>,
+}
+
+#[cfg(feature = "stats")]
+impl SearchStats {
+    #[inline(always)]
+    pub(super) fn count(&mut self, counter: Counter) {
+        self.counters[counter as usize] += 1;
+    }
+
+    #[inline(always)]
+    pub(super) fn end_iteration(&mut self, depth: u8, nodes: u64) {
+        self.iterations.push((depth, nodes));
+    }
+
+    pub fn get(&self, counter: Counter) -> u64 {
+        self.counters[counter as usize]
+    }
+
+    /// Depth and nodes searched by each iteration
+    pub fn iteration_nodes(&self) -> Vec<(u8, u64)> {
+        self.iterations
+            .iter()
+            .scan(0, |previous, &(depth, nodes)| {
+                let iteration_nodes = nodes - *previous;
+                *previous = nodes;
+                Some((depth, iteration_nodes))
+            })
+            .collect()
+    }
+}
+
+/// Nothing is collected without the stats feature
+#[cfg(not(feature = "stats"))]
+impl SearchStats {
+    #[inline(always)]
+    pub(super) fn count(&mut self, _counter: Counter) {}
+
+    #[inline(always)]
+    pub(super) fn end_iteration(&mut self, _depth: u8, _nodes: u64) {}
+
+    pub fn get(&self, _counter: Counter) -> u64 {
+        0
+    }
+
+    pub fn iteration_nodes(&self) -> Vec<(u8, u64)> {
+        Vec::new()
+    }
+}
+
+impl SearchStats {
+    pub const ENABLED: bool = cfg!(feature = "stats");
+
+    /// Average growth of the nodes of an iteration from one depth to the next
+    pub fn branching_factor(&self) -> Option<f64> {
+        let iterations = self.iteration_nodes();
+        let (&(first_depth, first_nodes), &(last_depth, last_nodes)) =
+            (iterations.first()?, iterations.last()?);
+        if last_depth <= first_depth || first_nodes == 0 {
+            return None;
+        }
+        let growth = last_nodes as f64 / first_nodes as f64;
+        Some(growth.powf(1.0 / (last_depth - first_depth) as f64))
+    }
+
+    /// `part` in percent of `total`
+    fn percent(&self, part: Counter, total: Counter) -> f64 {
+        match self.get(total) {
+            0 => 0.0,
+            total => self.get(part) as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Counter::*;
+
+        if !Self::ENABLED {
+            return writeln!(f, "Search statistics need the stats feature");
+        }
+        let nodes = self.get(Nodes) + self.get(QuiescenceNodes);
+        let quiescence_percent = match nodes {
+            0 => 0.0,
+            nodes => self.get(QuiescenceNodes) as f64 * 100.0 / nodes as f64,
+        };
+        writeln!(
+            f,
+            "Nodes: {} ({} quiescence, {:.1}%)",
+            nodes,
+            self.get(QuiescenceNodes),
+            quiescence_percent
+        )?;
+        writeln!(
+            f,
+            "TT: {} probes, {:.1}% hits, {:.1}% cutoffs",
+            self.get(TtProbes),
+            self.percent(TtHits, TtProbes),
+            self.percent(TtCutoffs, TtProbes)
+        )?;
+        writeln!(
+            f,
+            "Beta cutoffs: {}, {:.1}% by the first move",
+            self.get(BetaCutoffs),
+            self.percent(FirstMoveCutoffs, BetaCutoffs)
+        )?;
+        match self.branching_factor() {
+            Some(factor) => writeln!(f, "Branching factor: {:.2}", factor)?,
+            None => writeln!(f, "Branching factor: -")?,
+        }
+        let iterations: Vec<_> = self
+            .iteration_nodes()
+            .iter()
+            .map(|(depth, nodes)| format!("{}: {}", depth, nodes))
+            .collect();
+        writeln!(f, "Nodes per depth: {}", iterations.join(", "))?;
+        writeln!(
+            f,
+            "Pruned: mate distance {}, null move {}, reverse futility {}, futility {}, late move {}, delta {}, SEE {}",
+            self.get(MateDistancePrunes),
+            self.get(NullMovePrunes),
+            self.get(ReverseFutilityPrunes),
+            self.get(FutilityPrunes),
+            self.get(LateMovePrunes),
+            self.get(DeltaPrunes),
+            self.get(SeePrunes)
+        )?;
+        writeln!(
+            f,
+            "Late move reductions: {}, {:.1}% searched again",
+            self.get(LateMoveReductions),
+            self.percent(LateMoveResearches, LateMoveReductions)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chess_core::state::game_state::GameState;
+
+    use super::super::{search::SearchContext, transposition_table::TranspositionTable};
+    use super::*;
+
+    #[test]
+    fn test_search_stats() {
+        let state = &mut GameState::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1".to_string(),
+        );
+        let transpos = &TranspositionTable::new(1);
+        let mut search_ctx = SearchContext::new(state, transpos, None);
+        search_ctx.iterative_deepen_to_depth(5);
+        let stats = &search_ctx.stats;
+
+        if !SearchStats::ENABLED {
+            assert_eq!(stats.get(Counter::Nodes), 0);
+            assert_eq!(stats.branching_factor(), None);
+            assert_eq!(
+                stats.to_string(),
+                "Search statistics need the stats feature\n"
+            );
+            return;
+        }
+        assert_eq!(
+            stats.get(Counter::Nodes) + stats.get(Counter::QuiescenceNodes),
+            search_ctx.nodes
+        );
+        assert!(stats.get(Counter::TtHits) <= stats.get(Counter::TtProbes));
+        assert!(stats.get(Counter::TtCutoffs) <= stats.get(Counter::TtHits));
+        assert!(stats.get(Counter::FirstMoveCutoffs) <= stats.get(Counter::BetaCutoffs));
+        assert!(stats.get(Counter::FirstMoveCutoffs) > 0);
+        let iterations = stats.iteration_nodes();
+        assert_eq!(iterations.len(), 5);
+        assert_eq!(
+            iterations.iter().map(|&(_, nodes)| nodes).sum::<u64>(),
+            search_ctx.nodes
+        );
+        assert!(stats.branching_factor().unwrap() > 1.0);
+        assert!(stats.to_string().contains("Nodes per depth: 1: "));
+    }
+}
@@ -0,0 +1,486 @@
+// Strength limiting
+// A weaker engine for casual players, the Skill Level and UCI_Elo options of UCI. Below the
+// full strength level, the nodes and time of the search are cut by half every
+// LEVELS_PER_HALVING levels, a few lines are searched with MultiPV, and the move is picked among
+// them: each line gets a random bonus and a share of its score gap with the best line back. The
+// number of lines and the share shrink to nothing at full strength.
+//
+// Levels are tied to Elo ratings by self-play, see test_calibration: every level plays 200
+// games against full strength at 3000 nodes per move, from 100 random 8-ply openings played
+// with both colors. Adjacent levels are closer than the error bars, so the ratings lie on the
+// line fitted to the results. They are relative to FULL_STRENGTH_ELO, which is nominal. Last
+// results, with 95% confidence intervals:
+//   level  0:   6.0/200,  -604 Elo (-805 to -510)
+//   level  1:   7.0/200,  -576 Elo (-774 to -483)
+//   level  2:   6.0/200,  -604 Elo (-827 to -506)
+//   level  3:   6.0/200,  -604 Elo (-827 to -506)
+//   level  4:  11.0/200,  -494 Elo (-631 to -415)
+//   level  5:   8.5/200,  -541 Elo (-697 to -457)
+//   level  6:  10.0/200,  -512 Elo (-664 to -428)
+//   level  7:  11.5/200,  -486 Elo (-627 to -405)
+//   level  8:  12.5/200,  -470 Elo (-595 to -395)
+//   level  9:  16.0/200,  -424 Elo (-531 to -355)
+//   level 10:  15.0/200,  -436 Elo (-534 to -371)
+//   level 11:  19.0/200,  -392 Elo (-484 to -327)
+//   level 12:  21.5/200,  -368 Elo (-455 to -305)
+//   level 13:  33.5/200,  -279 Elo (-350 to -223)
+//   level 14:  39.0/200,  -246 Elo (-308 to -196)
+//   level 15:  38.5/200,  -249 Elo (-312 to -198)
+//   level 16:  42.5/200,  -228 Elo (-287 to -178)
+//   level 17:  50.0/200,  -191 Elo (-245 to -144)
+//   level 18:  58.5/200,  -153 Elo (-204 to -108)
+//   level 19:  69.5/200,  -109 Elo (-158 to -64)
+
+use chess_core::r#move::Move;
+use chrono::{Duration, Local};
+
+use super::{
+    evaluation::Evaluator,
+    search::{AnalysisLine, SearchContext},
+    search_limits::SearchLimits,
+};
+
+pub const MAX_LEVEL: u8 = 20;
+/// Rating given to the full strength level
+pub const FULL_STRENGTH_ELO: u32 = 2400;
+/// Rating of level 0
+pub const MIN_ELO: u32 = ELO_BY_LEVEL[0];
+
+/// Rating of each level, fitted to its results against full strength
+const ELO_BY_LEVEL: [u32; MAX_LEVEL as usize + 1] = [
+    1748,
+    1775,
+    1802,
+    1828,
+    1855,
+    1882,
+    1908,
+    1935,
+    1962,
+    1988,
+    2015,
+    2042,
+    2068,
+    2095,
+    2122,
+    2148,
+    2175,
+    2202,
+    2228,
+    2255,
+    FULL_STRENGTH_ELO,
+];
+
+/// The nodes and time of the search are cut by half every this many levels below full strength
+const LEVELS_PER_HALVING: f64 = 6.0;
+/// Number of lines the move is picked from at level 0, fewer at higher levels
+const SKILL_MULTI_PV: usize = 4;
+/// Share of the score gap with the best line given back at level 0, out of 128
+const MAX_WEAKNESS: f64 = 40.0;
+/// The random bonus is at most this score gap with the best line, in centipawns
+const MAX_RANDOM_GAP: i32 = 100;
+
+#[derive(Clone, Debug)]
+pub struct Skill {
+    /// From 0 to MAX_LEVEL, fractional levels come from Elo ratings between two levels
+    level: f64,
+    /// Xorshift state
+    random: u64,
+}
+
+impl Skill {
+    /// Skill Level, seeded by the clock so that games differ
+    pub fn new(level: u8) -> Self {
+        Skill::with_level(level.min(MAX_LEVEL) as f64)
+    }
+
+    /// UCI_Elo, clamped to the ratings of the levels
+    pub fn from_elo(elo: u32) -> Self {
+        let elo = elo.clamp(MIN_ELO, FULL_STRENGTH_ELO);
+        let level = ELO_BY_LEVEL
+            .windows(2)
+            .position(|ratings| elo <= ratings[1])
+            .map_or(MAX_LEVEL as f64, |level| {
+                let (low, high) = (ELO_BY_LEVEL[level], ELO_BY_LEVEL[level + 1]);
+                level as f64 + (elo - low) as f64 / (high - low) as f64
+            });
+        Skill::with_level(level)
+    }
+
+    fn with_level(level: f64) -> Self {
+        let nanos = Local::now().timestamp_subsec_nanos() as u64;
+        Skill {
+            level,
+            random: 0x9e37_79b9_7f4a_7c15 ^ nanos,
+        }
+    }
+
+    /// Same picks for the same searches
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.random = seed.max(1);
+        self
+    }
+
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    pub fn is_full_strength(&self) -> bool {
+        self.level >= MAX_LEVEL as f64
+    }
+
+    /// The limits of the search: the nodes and time are cut by half every LEVELS_PER_HALVING
+    /// levels below full strength, and the nodes are capped so that an unlimited search ends
+    pub fn limits(&self, limits: &SearchLimits) -> SearchLimits {
+        if self.is_full_strength() {
+            return limits.clone();
+        }
+        let share = 2f64.powf((self.level - MAX_LEVEL as f64) / LEVELS_PER_HALVING);
+        let max_nodes = 2f64.powf(9.0 + self.level / 2.0) as u64;
+        SearchLimits {
+            nodes: Some(limits.nodes.map_or(max_nodes, |nodes| {
+                ((nodes as f64 * share) as u64).clamp(1, max_nodes)
+            })),
+            move_time: limits.move_time.map(|time| {
+                Duration::microseconds(
+                    (time.num_microseconds().unwrap_or(i64::MAX) as f64 * share) as i64,
+                )
+            }),
+            ..limits.clone()
+        }
+    }
+
+    /// Searches and returns the line to play, the best one at full strength
+    pub fn search<E: Evaluator>(
+        &mut self,
+        search_ctx: &mut SearchContext<'_, E>,
+        limits: &SearchLimits,
+    ) -> AnalysisLine {
+        if self.is_full_strength() {
+            return search_ctx.analyse(limits).swap_remove(0);
+        }
+        let multi_pv = search_ctx.multi_pv;
+        let lines = 1 + ((SKILL_MULTI_PV - 1) as f64 * self.weakness()).ceil() as usize;
+        search_ctx.multi_pv = multi_pv.max(lines);
+        let lines = search_ctx.analyse(&self.limits(limits));
+        search_ctx.multi_pv = multi_pv;
+        self.pick(lines)
+    }
+
+    /// First move of the line to play
+    pub fn pick_move<E: Evaluator>(
+        &mut self,
+        search_ctx: &mut SearchContext<'_, E>,
+        limits: &SearchLimits,
+    ) -> Option<Move> {
+        self.search(search_ctx, limits).pv.first().copied()
+    }
+
+    /// Picks one of the lines, sorted best first. A line gets a share of its gap with the
+    /// best line back and a random bonus, the line with the highest total is picked.
+    fn pick(&mut self, lines: Vec<AnalysisLine>) -> AnalysisLine {
+        // Out of 128
+        let weakness = MAX_WEAKNESS * self.weakness();
+        let best = lines[0].score;
+        let spread = (best - lines[lines.len() - 1].score).min(MAX_RANDOM_GAP);
+        lines
+            .into_iter()
+            .max_by_key(|line| {
+                let gap = (best - line.score) as f64;
+                let random = self.below(weakness as u64) as f64;
+                let bonus = (weakness * gap + spread as f64 * random) / 128.0;
+                line.score + bonus as i32
+            })
+            .unwrap()
+    }
+
+    /// From 1 at level 0 down to 0 at full strength
+    fn weakness(&self) -> f64 {
+        1.0 - self.level / MAX_LEVEL as f64
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random % n.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chess_core::{
+        color::Color,
+        r#move::{MoveCode, MoveGenerator},
+        square::Square,
+        state::{game_state::GameState, make_unmake::MakeUnmaker},
+    };
+
+    use super::super::{search::tests::TestPosition, transposition_table::TranspositionTable};
+    use super::*;
+
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    /// Games still going on are drawn after this many plies
+    const MAX_GAME_PLIES: usize = 300;
+
+    fn line(score: i32, to: u8) -> AnalysisLine {
+        AnalysisLine {
+            depth: 4,
+            score,
+            pv: vec![Move::new(Square(8), Square(to), MoveCode::QuietMove)],
+        }
+    }
+
+    #[test]
+    fn test_levels() {
+        assert!(Skill::from_elo(FULL_STRENGTH_ELO).is_full_strength());
+        assert!(Skill::from_elo(5000).is_full_strength());
+        assert_eq!(Skill::from_elo(0).level(), 0.0);
+        assert_eq!(Skill::from_elo(ELO_BY_LEVEL[7]).level(), 7.0);
+        let between = (ELO_BY_LEVEL[7] + ELO_BY_LEVEL[8]) / 2;
+        assert!((Skill::from_elo(between).level() - 7.5).abs() < 0.05);
+        assert!(
+            ELO_BY_LEVEL
+                .windows(2)
+                .all(|ratings| ratings[0] < ratings[1])
+        );
+
+        // The given nodes and time are cut, and the nodes are capped
+        let limits = Skill::new(3).limits(&SearchLimits::default());
+        assert_eq!(limits.nodes, Some(1448));
+        let limits = Skill::new(14).limits(&SearchLimits::nodes(3000));
+        assert_eq!(limits.nodes, Some(1500));
+        let limits = Skill::new(14).limits(&SearchLimits::move_time(Duration::seconds(1)));
+        assert_eq!(limits.move_time, Some(Duration::milliseconds(500)));
+        let limits = Skill::new(3).limits(&SearchLimits::nodes(1_000_000));
+        assert_eq!(limits.nodes, Some(1448));
+        let limits = Skill::new(3).limits(&SearchLimits::depth(2));
+        assert_eq!(limits.depth, Some(2));
+        assert_eq!(
+            Skill::new(MAX_LEVEL).limits(&SearchLimits::default()),
+            SearchLimits::default()
+        );
+    }
+
+    #[test]
+    fn test_pick() {
+        let lines = || vec![line(50, 16), line(40, 17), line(30, 18), line(-20, 19)];
+        let picks = |level| {
+            let mut skill = Skill::new(level).with_seed(1);
+            let mut counts = [0; 4];
+            for _ in 0..1000 {
+                let picked = skill.pick(lines());
+                counts[lines().iter().position(|l| *l == picked).unwrap()] += 1;
+            }
+            counts
+        };
+        // Lower levels pick the best line less often
+        let (weak, strong) = (picks(0), picks(19));
+        assert!(weak[0] < strong[0], "{:?} {:?}", weak, strong);
+        assert!(weak[1] > 0 && weak[2] > 0, "{:?}", weak);
+        assert_eq!(weak[3], 0);
+        assert_eq!(strong[0], 1000);
+
+        // Winning material is not missed from the middle levels
+        let mut skill = Skill::new(10).with_seed(1);
+        for _ in 0..100 {
+            let picked = skill.pick(vec![line(900, 16), line(0, 17), line(-10, 18)]);
+            assert_eq!(picked.score, 900);
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        // Nc7+ forks the king and the rook
+        let fork = Move::new(Square(33), Square(50), MoveCode::QuietMove);
+        let picks = |level| -> Vec<Move> {
+            (0..10)
+                .map(|seed| {
+                    let position = &mut TestPosition::new("r3k3/7p/8/1N6/8/8/7P/4K3 w - - 0 1");
+                    let mut search_ctx = position.search_ctx();
+                    let mut skill = Skill::new(level).with_seed(seed);
+                    let line = skill.search(&mut search_ctx, &SearchLimits::depth(4));
+                    assert_eq!(search_ctx.multi_pv, 1);
+                    *line.pv.first().unwrap()
+                })
+                .collect()
+        };
+        // Even the lowest level does not miss it
+        for level in [0, 5, 10, 15, MAX_LEVEL] {
+            assert!(picks(level).iter().all(|&m| m == fork), "level {}", level);
+        }
+    }
+
+    /// Unlike the search, which scores the first repetition as a draw, games go on until the
+    /// third occurrence so that the weaker side does not save them by repeating once
+    fn is_threefold_repetition(make_unmaker: &MakeUnmaker) -> bool {
+        let history = &make_unmaker.hash_history;
+        let occurrences = history
+            .iter()
+            .rev()
+            .take(make_unmaker.state.halfmove as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|&&hash| hash == make_unmaker.zobrist_hash)
+            .count();
+        occurrences >= 2
+    }
+
+    fn legal_moves(move_generator: &MoveGenerator, make_unmaker: &mut MakeUnmaker) -> Vec<Move> {
+        let mut moves = Vec::new();
+        move_generator.get_pseudo_legal_moves(make_unmaker.state, &mut moves);
+        moves.retain(|&m| {
+            make_unmaker.make_move(m);
+            let legal = move_generator.was_move_legal(make_unmaker.state);
+            make_unmaker.unmake_move(m);
+            legal
+        });
+        moves
+    }
+
+    /// Random legal moves from the starting position, drawn with a xorshift
+    fn random_opening(random: &mut u64, plies: usize) -> Vec<Move> {
+        let move_generator = &MoveGenerator::new();
+        let state = &mut GameState::from_fen(START_FEN.to_string());
+        let make_unmaker = &mut MakeUnmaker::new(state);
+        let mut opening = Vec::new();
+        for _ in 0..plies {
+            *random ^= *random << 13;
+            *random ^= *random >> 7;
+            *random ^= *random << 17;
+            let moves = legal_moves(move_generator, make_unmaker);
+            let m = moves[(*random % moves.len() as u64) as usize];
+            make_unmaker.make_move(m);
+            opening.push(m);
+        }
+        opening
+    }
+
+    /// Plays a game after the opening, white first in `players`, and returns the score of white
+    fn play_game(
+        opening: &[Move],
+        players: &mut [(Skill, TranspositionTable); 2],
+        nodes: u64,
+    ) -> f64 {
+        let move_generator = &MoveGenerator::new();
+        let state = &mut GameState::from_fen(START_FEN.to_string());
+        let make_unmaker = &mut MakeUnmaker::new(state);
+        for &m in opening {
+            make_unmaker.make_move(m);
+        }
+        for _ in 0..MAX_GAME_PLIES {
+            let color = make_unmaker.state.flags.active_color();
+            if legal_moves(move_generator, make_unmaker).is_empty() {
+                return match (move_generator.is_check(make_unmaker.state), color) {
+                    (false, _) => 0.5,
+                    (true, Color::White) => 0.0,
+                    (true, Color::Black) => 1.0,
+                };
+            }
+            if make_unmaker.state.halfmove >= 100 || is_threefold_repetition(make_unmaker) {
+                return 0.5;
+            }
+            let (skill, transpos) = &mut players[(color == Color::Black) as usize];
+            let mut root = *make_unmaker.state;
+            let search_ctx = &mut SearchContext::new(&mut root, transpos, None);
+            search_ctx.make_unmaker.hash_history = make_unmaker.hash_history.clone();
+            let m = skill
+                .pick_move(search_ctx, &SearchLimits::nodes(nodes))
+                .unwrap();
+            make_unmaker.make_move(m);
+        }
+        0.5
+    }
+
+    /// Plays the matches ELO_BY_LEVEL comes from and prints their results and the ratings of
+    /// the levels, in release mode it takes about a quarter of an hour:
+    /// cargo test --release -p chess_engines test_calibration -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn test_calibration() {
+        let (openings, nodes) = (100, 3000);
+        let random = &mut 12345;
+        let openings: Vec<_> = (0..openings).map(|_| random_opening(random, 8)).collect();
+        // Elo difference with full strength of each level, with its 95% confidence interval
+        let mut differences = Vec::new();
+        for level in 0..MAX_LEVEL {
+            let mut results = Vec::new();
+            for (i, opening) in openings.iter().enumerate() {
+                for level_is_white in [true, false] {
+                    let seed = 2 * i as u64 + level_is_white as u64 + 1;
+                    let player = (
+                        Skill::new(level).with_seed(seed),
+                        TranspositionTable::new(1),
+                    );
+                    let full_strength = (
+                        Skill::new(MAX_LEVEL).with_seed(seed),
+                        TranspositionTable::new(1),
+                    );
+                    results.push(if level_is_white {
+                        play_game(opening, &mut [player, full_strength], nodes)
+                    } else {
+                        1.0 - play_game(opening, &mut [full_strength, player], nodes)
+                    });
+                }
+            }
+            let games = results.len() as f64;
+            let score: f64 = results.iter().sum();
+            let share = score / games;
+            // From the spread of the game results
+            let variance = results.iter().map(|r| (r - share).powi(2)).sum::<f64>() / games;
+            let margin = 1.96 * (variance / games).sqrt();
+            // Half a game is given to either side so that the difference is finite
+            let elo = |share: f64| {
+                let share = share.clamp(0.5 / games, 1.0 - 0.5 / games);
+                400.0 * (share / (1.0 - share)).log10()
+            };
+            let difference = (elo(share), elo(share - margin), elo(share + margin));
+            println!(
+                "level {:2}: {:5.1}/{}, {:+5.0} Elo ({:+.0} to {:+.0})",
+                level, score, games, difference.0, difference.1, difference.2
+            );
+            differences.push(difference);
+        }
+        // Adjacent levels are closer than the error bars, so the ratings lie on the line fitted
+        // to the differences rather than on the differences themselves
+        let n = differences.len() as f64;
+        let mean_level = (n - 1.0) / 2.0;
+        let mean_difference = differences.iter().map(|d| d.0).sum::<f64>() / n;
+        let slope = differences
+            .iter()
+            .enumerate()
+            .map(|(level, d)| (level as f64 - mean_level) * (d.0 - mean_difference))
+            .sum::<f64>()
+            / differences
+                .iter()
+                .enumerate()
+                .map(|(level, _)| (level as f64 - mean_level).powi(2))
+                .sum::<f64>();
+        let ratings: Vec<_> = (0..MAX_LEVEL as usize)
+            .map(|level| {
+                let difference = mean_difference + slope * (level as f64 - mean_level);
+                (FULL_STRENGTH_ELO as f64 + difference).round() as u32
+            })
+            .chain([FULL_STRENGTH_ELO])
+            .collect();
+        println!("{:?}", ratings);
+        for (level, &(_, low, _)) in differences.iter().enumerate() {
+            for (higher, &(_, _, high)) in differences.iter().enumerate().skip(level + 1) {
+                assert!(
+                    low <= high,
+                    "level {} is stronger than level {}",
+                    level,
+                    higher
+                );
+            }
+        }
+        for level in 1..=MAX_LEVEL as usize {
+            assert!(
+                ratings[level] > ratings[level - 1],
+                "level {} is not stronger than level {}",
+                level,
+                level - 1
+            );
+        }
+    }
+}
@@ -1,45 +1,324 @@
 // We need a high performance, fixed size, hash table
-// For now use a fixed size array and address it with hash % size
-// The value will be a struct with hash, depth, score, best_move
+// The table is a flat array of entries addressed with hash % size, and its size is given
+// in megabytes so that it can be set at runtime (UCI Hash option).
+// Entries are tagged with the generation of the search that wrote them: entries from
+// earlier moves of the same game survive and can still be used, but are replaced first.
+// The table is shared between search threads without locking: each entry is stored as
+// atomic words and the key is xored with the data, so a torn write is seen as a miss.
 
-use boxarray::boxarray;
+use std::{
+    mem::size_of,
+    sync::atomic::{AtomicU8, AtomicU32, Ordering},
+};
 
 use chess_core::r#move::Move;
 
-const TABLE_SIZE: usize = 1 << 20;
+pub const DEFAULT_SIZE_MB: usize = 16;
 
+const BOUND_MASK: u8 = 0b11;
+const GENERATION_SHIFT: u8 = 2;
+const GENERATION_MASK: u8 = 0b11_1111;
+
+/// Number of entries looked at to estimate how full the table is
+const HASHFULL_SAMPLE: usize = 1000;
+
+/// Relation of a stored score to the true score of the position
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact
+    Exact = 1,
+    /// The search failed high, the true score is at least the stored score
+    Lower = 2,
+    /// The search failed low, the true score is at most the stored score
+    Upper = 3,
+}
+
+impl Bound {
+    const fn from_bits(bits: u8) -> Self {
+        match bits {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// A decoded table entry.
+///
+/// Only the upper 32 bits of the hash are stored to verify the key, the index of the entry
+/// already depends on the lower bits. The bound and the generation share a single byte, an
+/// entry with no bound is empty.
 #[derive(Copy, Clone)]
-#[allow(dead_code)]
 pub struct TtEntry {
-    pub hash: u64,
-    pub depth: u8,
-    pub score: i32,
-    pub best_move: Move,
+    key: u32,
+    best_move: Move,
+    depth: u8,
+    generation_bound: u8,
+    score: i32,
+}
+
+impl TtEntry {
+    fn key_from_hash(hash: u64) -> u32 {
+        (hash >> 32) as u32
+    }
+
+    /// Packs everything but the key in 64 bits
+    fn data(&self) -> u64 {
+        (self.best_move.into_bits() as u64)
+            | (self.depth as u64) << 16
+            | (self.generation_bound as u64) << 24
+            | (self.score as u32 as u64) << 32
+    }
+
+    fn from_data(key: u32, data: u64) -> Self {
+        TtEntry {
+            key,
+            best_move: Move::from_bits(data as u16),
+            depth: (data >> 16) as u8,
+            generation_bound: (data >> 24) as u8,
+            score: (data >> 32) as u32 as i32,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.generation_bound & BOUND_MASK == 0
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation_bound >> GENERATION_SHIFT
+    }
+
+    /// Remaining depth of the search that produced this entry
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    pub fn bound(&self) -> Bound {
+        Bound::from_bits(self.generation_bound & BOUND_MASK)
+    }
+
+    pub fn best_move(&self) -> Option<Move> {
+        if self.best_move.into_bits() == 0 {
+            None
+        } else {
+            Some(self.best_move)
+        }
+    }
+}
+
+/// A 12 byte slot of the table holding one entry
+#[derive(Default)]
+struct TtSlot {
+    /// Key xored with both data words
+    checked_key: AtomicU32,
+    data_low: AtomicU32,
+    data_high: AtomicU32,
+}
+
+impl TtSlot {
+    fn load(&self) -> TtEntry {
+        let data_low = self.data_low.load(Ordering::Relaxed);
+        let data_high = self.data_high.load(Ordering::Relaxed);
+        let key = self.checked_key.load(Ordering::Relaxed) ^ data_low ^ data_high;
+        TtEntry::from_data(key, (data_high as u64) << 32 | data_low as u64)
+    }
+
+    fn save(&self, entry: TtEntry) {
+        let data = entry.data();
+        let (data_low, data_high) = (data as u32, (data >> 32) as u32);
+        self.checked_key
+            .store(entry.key ^ data_low ^ data_high, Ordering::Relaxed);
+        self.data_low.store(data_low, Ordering::Relaxed);
+        self.data_high.store(data_high, Ordering::Relaxed);
+    }
 }
 
 pub struct TranspositionTable {
-    table: Box<[Option<TtEntry>; TABLE_SIZE]>,
+    table: Box<[TtSlot]>,
+    generation: AtomicU8,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIZE_MB)
+    }
 }
 
-#[allow(dead_code)]
 impl TranspositionTable {
-    pub fn new() -> Self {
+    /// Allocates a table using at most `size_mb` megabytes (and at least one entry)
+    pub fn new(size_mb: usize) -> Self {
+        let entry_number = (size_mb * 1024 * 1024 / size_of::<TtSlot>()).max(1);
         TranspositionTable {
-            table: boxarray(None),
+            table: (0..entry_number).map(|_| TtSlot::default()).collect(),
+            generation: AtomicU8::new(0),
         }
     }
 
-    pub fn store(&mut self, entry: TtEntry) {
-        let index = entry.hash as usize % TABLE_SIZE;
-        self.table[index] = Some(entry);
+    /// Reallocates the table, all entries are lost
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = Self::new(size_mb);
+    }
+
+    /// Empties the table, to be used when starting a new game
+    pub fn clear(&mut self) {
+        for slot in self.table.iter_mut() {
+            *slot = TtSlot::default();
+        }
+        *self.generation.get_mut() = 0;
+    }
+
+    /// Ages the entries already in the table, to be called once per move searched
+    pub fn new_search(&self) {
+        let generation = self.generation();
+        self.generation
+            .store((generation + 1) & GENERATION_MASK, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        (hash % self.table.len() as u64) as usize
+    }
+
+    /// Stores an entry, replacing the current one if it is from an older search, if it was
+    /// searched less deeply or if the new score is exact.
+    pub fn store(&self, hash: u64, depth: u8, score: i32, bound: Bound, best_move: Option<Move>) {
+        let slot = &self.table[self.index(hash)];
+        let key = TtEntry::key_from_hash(hash);
+        let entry = slot.load();
+        let generation = self.generation();
+
+        let replace = entry.is_empty()
+            || entry.generation() != generation
+            || bound == Bound::Exact
+            || depth >= entry.depth;
+        if !replace {
+            return;
+        }
+
+        // Keep the previous best move of the same position if the new search has none
+        let best_move = match best_move {
+            Some(m) => m,
+            None if entry.key == key => entry.best_move,
+            None => Move::from_bits(0),
+        };
+
+        slot.save(TtEntry {
+            key,
+            best_move,
+            depth,
+            generation_bound: (generation << GENERATION_SHIFT) | bound as u8,
+            score,
+        });
+    }
+
+    pub fn get(&self, hash: u64) -> Option<TtEntry> {
+        let entry = self.table[self.index(hash)].load();
+        if !entry.is_empty() && entry.key == TtEntry::key_from_hash(hash) {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Estimation of the proportion of the table used by the current search, in permille
+    pub fn hashfull(&self) -> usize {
+        let sample = &self.table[..HASHFULL_SAMPLE.min(self.table.len())];
+        let generation = self.generation();
+        let used = sample
+            .iter()
+            .map(TtSlot::load)
+            .filter(|e| !e.is_empty() && e.generation() == generation)
+            .count();
+        used * 1000 / sample.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chess_core::{r#move::MoveCode, square::Square};
+
+    use super::*;
+
+    #[test]
+    fn test_entry_size() {
+        assert_eq!(size_of::<TtSlot>(), 12);
+        assert_eq!(TranspositionTable::new(1).len(), 1024 * 1024 / 12);
+    }
+
+    #[test]
+    fn test_store_get() {
+        let tt = &mut TranspositionTable::new(1);
+        let m = Move::new(Square(12), Square(28), MoveCode::DoublePawnPush);
+        let hash = 0x1234_5678_9abc_def0;
+
+        assert!(tt.get(hash).is_none());
+        tt.store(hash, 4, 35, Bound::Exact, Some(m));
+        let entry = tt.get(hash).unwrap();
+        assert_eq!(entry.depth(), 4);
+        assert_eq!(entry.score(), 35);
+        assert_eq!(entry.bound(), Bound::Exact);
+        assert_eq!(entry.best_move(), Some(m));
+
+        // Same index but different key
+        let other_hash = hash + ((tt.len() as u64) << 32);
+        assert_eq!(tt.index(hash), tt.index(other_hash));
+        assert!(tt.get(other_hash).is_none());
+
+        // Shallower search of the same generation does not replace
+        tt.store(other_hash, 2, -10, Bound::Lower, None);
+        assert!(tt.get(other_hash).is_none());
+
+        // Entries of older searches are replaced
+        tt.new_search();
+        tt.store(other_hash, 2, -10, Bound::Upper, None);
+        assert!(tt.get(hash).is_none());
+        let entry = tt.get(other_hash).unwrap();
+        assert_eq!(entry.bound(), Bound::Upper);
+        assert_eq!(entry.best_move(), None);
+
+        tt.clear();
+        assert!(tt.get(other_hash).is_none());
+    }
+
+    #[test]
+    fn test_torn_entry_is_a_miss() {
+        let tt = TranspositionTable::new(1);
+        let hash = 0x1234_5678_9abc_def0;
+        tt.store(hash, 4, 35, Bound::Exact, None);
+        assert!(tt.get(hash).is_some());
+
+        // Another thread only wrote half of its entry
+        let slot = &tt.table[tt.index(hash)];
+        slot.data_high.store(-35_i32 as u32, Ordering::Relaxed);
+        assert!(tt.get(hash).is_none());
     }
 
-    pub fn get(&self, hash: u64) -> Option<&TtEntry> {
-        let index = hash as usize % TABLE_SIZE;
-        let entry = &self.table[index];
-        match entry {
-            Some(e) if e.hash == hash => Some(e),
-            _ => None,
+    #[test]
+    fn test_hashfull() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        for hash in 0..500 {
+            tt.store(hash, 1, 0, Bound::Exact, None);
         }
+        assert_eq!(tt.hashfull(), 500);
+        // Entries from previous searches do not count
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
     }
 }
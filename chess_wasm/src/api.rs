@@ -2,8 +2,16 @@ use chess_core::{
     r#move::{MoveGenerator, MoveList},
     state::{game_state::GameState, make_unmake::MakeUnmaker},
 };
-use chess_engines::alpha_beta::search::SearchContext;
+use chess_engines::alpha_beta::{
+    evaluation::{EvalTrace, SimpleEvaluator},
+    opening_book::{OpeningBook, game_ply},
+    search::SearchContext,
+    search_limits::SearchLimits,
+    skill::{MAX_LEVEL, Skill},
+    transposition_table::{DEFAULT_SIZE_MB, TranspositionTable},
+};
 use chrono::Duration;
+use std::cell::{Cell, RefCell};
 
 use serde::{Deserialize, Serialize};
 
@@ -13,20 +21,76 @@ pub struct EvaluationResult {
     pub best_move: String, // TODO: change to pv
 }
 
+/// Static evaluation of a position, term by term
+#[derive(Serialize)]
+pub struct EvalTraceResult {
+    pub trace: EvalTrace,
+    /// The trace printed as a table
+    pub table: String,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct FullGameState {
     pub fen: String,
     pub pgn: String,
 }
 
+thread_local! {
+    // Kept between calls so that the entries of previous moves of the game can be reused
+    static TRANSPOSITION_TABLE: RefCell<TranspositionTable> =
+        RefCell::new(TranspositionTable::new(DEFAULT_SIZE_MB));
+    // Strength of the replies, full strength unless set
+    static SKILL: RefCell<Skill> = RefCell::new(Skill::new(MAX_LEVEL));
+    // The built-in book, compiled into the module
+    static BOOK: RefCell<OpeningBook> = RefCell::new(OpeningBook::builtin());
+    // On by default, unlike the UCI OwnBook option: a UCI GUI usually plays from its own book,
+    // the web app has no other book than this one
+    static OWN_BOOK: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Forgets everything learned about the previous game
+pub fn new_game() {
+    TRANSPOSITION_TABLE.with_borrow_mut(|transpos| transpos.clear());
+}
+
+pub fn set_hash_size(size_mb: usize) {
+    TRANSPOSITION_TABLE.with_borrow_mut(|transpos| transpos.resize(size_mb));
+}
+
+/// From 0 to 20, the full strength
+pub fn set_skill_level(level: u8) {
+    SKILL.set(Skill::new(level));
+}
+
+/// Strength as an Elo rating, see Skill::from_elo
+pub fn set_elo(elo: u32) {
+    SKILL.set(Skill::from_elo(elo));
+}
+
+/// Whether respond plays book moves in the opening, the default
+pub fn set_own_book(enabled: bool) {
+    OWN_BOOK.set(enabled);
+}
+
 pub fn evaluate(fgs: FullGameState) -> EvaluationResult {
     let state = &mut GameState::from_fen(fgs.fen);
-    let search_ctx = &mut SearchContext::new(state, None);
-    let (score, pv) = search_ctx.iterative_deepen(Duration::new(1, 0).unwrap());
+    let (score, pv) = TRANSPOSITION_TABLE.with_borrow(|transpos| {
+        let search_ctx = &mut SearchContext::new(state, transpos, None);
+        search_ctx.iterative_deepen(Duration::new(1, 0).unwrap())
+    });
 
     EvaluationResult {
         score,
-        best_move: format!("{}", pv.last().unwrap()),
+        best_move: format!("{}", pv.first().unwrap()),
+    }
+}
+
+pub fn eval_trace(fen: String) -> EvalTraceResult {
+    let state = &GameState::from_fen(fen);
+    let trace = SimpleEvaluator::default().eval_trace(state);
+    EvalTraceResult {
+        table: trace.to_string(),
+        trace,
     }
 }
 
@@ -40,7 +104,7 @@ pub fn is_move_legal(fen: String, r#move: String) -> bool {
     move_generator.get_pseudo_legal_moves(make_unmaker.state, move_list);
     let pseudo_legal_move = move_list
         .current_ply()
-        .into_iter()
+        .iter()
         .find(|m| m.matches_perft_string(r#move.split_at(4).0));
     if let Some(pseudo_legal_move) = pseudo_legal_move {
         make_unmaker.make_move(*pseudo_legal_move);
@@ -59,7 +123,7 @@ pub fn needs_promotion(fen: String, r#move: String) -> bool {
     move_generator.get_pseudo_legal_moves(make_unmaker.state, move_list);
     move_list
         .current_ply()
-        .into_iter()
+        .iter()
         .find(|m| m.matches_perft_string(r#move.split_at(4).0))
         .unwrap()
         .code()
@@ -85,11 +149,21 @@ pub fn make_move(fgs: FullGameState, r#move: String) -> FullGameState {
 }
 
 pub fn respond(fgs: FullGameState) -> FullGameState {
+    let ply = game_ply(&fgs.fen);
     let state = &mut GameState::from_fen(fgs.fen);
-    let search_ctx = &mut SearchContext::new(state, None);
-    let (_, m) = search_ctx.iterative_deepen(Duration::new(0, 300_000_000).unwrap());
+    let book_move = match OWN_BOOK.get() {
+        true => BOOK.with_borrow_mut(|book| book.probe(state, ply)),
+        false => None,
+    };
+    let m = book_move.or_else(|| {
+        TRANSPOSITION_TABLE.with_borrow(|transpos| {
+            let search_ctx = &mut SearchContext::new(state, transpos, None);
+            let limits = SearchLimits::move_time(Duration::new(0, 300_000_000).unwrap());
+            SKILL.with_borrow_mut(|skill| skill.pick_move(search_ctx, &limits))
+        })
+    });
     let make_unmaker = &mut MakeUnmaker::new(state);
-    make_unmaker.make_move(*m.last().unwrap());
+    make_unmaker.make_move(m.unwrap());
     FullGameState {
         fen: state.to_fen(),
         pgn: "".to_string(),
@@ -108,7 +182,34 @@ mod tests {
         };
         let res = evaluate(fgs);
         println!("{}", res.best_move);
-        assert_eq!(res.score, 35);
+        // The depth reached in a second varies, and with it the exact score
+        assert!((0..100).contains(&res.score), "{}", res.score);
+    }
+
+    #[test]
+    fn test_eval_trace() {
+        let res =
+            eval_trace("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string());
+        assert_eq!(res.trace.score, 0);
+        assert!(res.table.contains("Material"));
+    }
+
+    #[test]
+    fn test_respond_from_book() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let fgs = FullGameState {
+            fen: fen.to_string(),
+            pgn: String::new(),
+        };
+        // One of the first moves of the built-in book, without searching
+        let board = respond(fgs).fen.split(' ').next().unwrap().to_string();
+        let book_boards = [
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR",
+            "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR",
+            "rnbqkbnr/pppppppp/8/8/2P5/8/PP1PPPPP/RNBQKBNR",
+            "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R",
+        ];
+        assert!(book_boards.contains(&board.as_str()), "{}", board);
     }
 
     #[test]
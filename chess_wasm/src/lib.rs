@@ -15,6 +15,15 @@ pub fn evaluate(fgs: JsValue) -> JsValue {
     serde_wasm_bindgen::to_value(&result).unwrap()
 }
 
+#[wasm_bindgen]
+pub fn eval_trace(fen: String) -> JsValue {
+    set_panic_hook();
+
+    let result = api::eval_trace(fen);
+
+    serde_wasm_bindgen::to_value(&result).unwrap()
+}
+
 #[wasm_bindgen]
 pub fn is_move_legal(fen: String, r#move: String) -> bool {
     set_panic_hook();
@@ -48,3 +57,38 @@ pub fn respond(fgs: JsValue) -> JsValue {
 
     serde_wasm_bindgen::to_value(&result).unwrap()
 }
+
+#[wasm_bindgen]
+pub fn new_game() {
+    set_panic_hook();
+
+    api::new_game()
+}
+
+#[wasm_bindgen]
+pub fn set_hash_size(size_mb: usize) {
+    set_panic_hook();
+
+    api::set_hash_size(size_mb)
+}
+
+#[wasm_bindgen]
+pub fn set_skill_level(level: u8) {
+    set_panic_hook();
+
+    api::set_skill_level(level)
+}
+
+#[wasm_bindgen]
+pub fn set_elo(elo: u32) {
+    set_panic_hook();
+
+    api::set_elo(elo)
+}
+
+#[wasm_bindgen]
+pub fn set_own_book(enabled: bool) {
+    set_panic_hook();
+
+    api::set_own_book(enabled)
+}